@@ -0,0 +1,172 @@
+//! Task admission control for `server.max_concurrent_tasks` and per-agent caps
+
+use crate::config::AgentConfig;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub struct AdmissionController {
+    max_concurrent: usize,
+    global: Arc<Semaphore>,
+    /// Per-agent semaphores, keyed by agent ID with the cap they were built for
+    per_agent: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// Held for as long as a task occupies an execution slot
+pub struct AdmissionPermit {
+    _agent: Option<OwnedSemaphorePermit>,
+    _global: OwnedSemaphorePermit,
+}
+
+/// Keeps the queue depth accurate even if the waiting future is dropped
+struct QueuedGuard(Arc<AtomicUsize>);
+
+impl QueuedGuard {
+    fn new(queued: &Arc<AtomicUsize>) -> Self {
+        queued.fetch_add(1, Ordering::SeqCst);
+        Self(queued.clone())
+    }
+}
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl AdmissionController {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent,
+            global: Arc::new(Semaphore::new(max_concurrent)),
+            per_agent: Mutex::new(HashMap::new()),
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Wait until both a global slot and a slot on `agent` are free
+    pub async fn admit(&self, agent: &AgentConfig) -> AdmissionPermit {
+        let _queued = QueuedGuard::new(&self.queued);
+
+        // Take the agent slot first so a task waiting on a busy agent
+        // doesn't hold a global slot other agents could use
+        let agent_permit = match self.agent_semaphore(agent) {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("admission semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        let global_permit = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("admission semaphore is never closed");
+
+        AdmissionPermit {
+            _agent: agent_permit,
+            _global: global_permit,
+        }
+    }
+
+    /// Tasks waiting for a slot
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Tasks currently holding a slot
+    pub fn running(&self) -> usize {
+        self.max_concurrent - self.global.available_permits()
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    fn agent_semaphore(&self, agent: &AgentConfig) -> Option<Arc<Semaphore>> {
+        let cap = agent.max_concurrent_tasks?;
+        let mut per_agent = self.per_agent.lock().unwrap_or_else(|e| e.into_inner());
+
+        let entry = per_agent
+            .entry(agent.id.clone())
+            .or_insert_with(|| (cap, Arc::new(Semaphore::new(cap))));
+
+        // Cap changed: new tasks use a fresh semaphore, running ones keep theirs
+        if entry.0 != cap {
+            *entry = (cap, Arc::new(Semaphore::new(cap)));
+        }
+
+        Some(entry.1.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn create_test_agent(id: &str, max_concurrent_tasks: Option<usize>) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: "cli".to_string(),
+            max_concurrent_tasks,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_global_limit_queues_tasks() {
+        let controller = Arc::new(AdmissionController::new(1));
+        let agent = create_test_agent("agent", None);
+
+        let first = controller.admit(&agent).await;
+        assert_eq!(controller.running(), 1);
+
+        let waiting = {
+            let controller = controller.clone();
+            let agent = agent.clone();
+            tokio::spawn(async move { controller.admit(&agent).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(controller.queue_depth(), 1);
+
+        drop(first);
+        let _second = waiting.await.unwrap();
+        assert_eq!(controller.queue_depth(), 0);
+        assert_eq!(controller.running(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_per_agent_cap() {
+        let controller = Arc::new(AdmissionController::new(5));
+        let capped = create_test_agent("capped", Some(1));
+        let other = create_test_agent("other", None);
+
+        let _first = controller.admit(&capped).await;
+
+        let waiting = {
+            let controller = controller.clone();
+            let capped = capped.clone();
+            tokio::spawn(async move { controller.admit(&capped).await })
+        };
+
+        // Other agents are not blocked by the capped one
+        let _other = tokio::time::timeout(Duration::from_millis(100), controller.admit(&other))
+            .await
+            .expect("uncapped agent should be admitted");
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(controller.queue_depth(), 1);
+        assert!(!waiting.is_finished());
+        waiting.abort();
+    }
+}
@@ -49,7 +49,7 @@ impl AgentCreator {
     pub async fn create_agent(
         &self,
         requirements: &str,
-        context: Option<Value>,
+        _context: Option<Value>,
     ) -> Result<AgentSpec> {
         tracing::info!("🤖 Agent Creator: Generating agent from requirements");
         tracing::debug!("Requirements: {}", requirements);
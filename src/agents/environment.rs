@@ -0,0 +1,133 @@
+//! Process environment and working directory of CLI agents, so each agent
+//! only sees its own credentials
+
+use crate::config::{expand_tilde, AgentConfig};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Apply the agent's `clear_env`, `env_remove`, `env`, `secrets_from_file`
+/// and `cwd` settings, in that order (secrets win over `env`).
+///
+/// Without `clear_env` the agent inherits the server's whole environment,
+/// including other agents' credentials; list those in `env_remove`.
+pub async fn apply(command: &mut Command, agent: &AgentConfig) -> Result<()> {
+    if agent.clear_env {
+        command.env_clear();
+    }
+
+    for key in &agent.env_remove {
+        command.env_remove(key);
+    }
+
+    command.envs(&agent.env);
+
+    if let Some(path) = &agent.secrets_from_file {
+        let path = expand_tilde(path);
+        // Read off the runtime threads: the file is read again on every spawn
+        let secrets = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || load_secrets(&path)
+        })
+        .await
+        .context("Secrets reader panicked")?
+        .with_context(|| format!("Failed to load secrets for agent '{}' from {:?}", agent.id, path))?;
+        command.envs(secrets);
+    }
+
+    if let Some(cwd) = &agent.cwd {
+        let cwd = expand_tilde(cwd);
+        if !cwd.is_dir() {
+            bail!("Working directory of agent '{}' not found: {:?}", agent.id, cwd);
+        }
+        command.current_dir(cwd);
+    }
+
+    Ok(())
+}
+
+/// Read a dotenv-style file: `KEY=VALUE` per line, `#` comments,
+/// optional `export ` prefix and optional surrounding quotes
+pub fn load_secrets(path: &Path) -> Result<Vec<(String, String)>> {
+    warn_if_shared(path);
+
+    let content = fs::read_to_string(path)?;
+    let mut secrets = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        // Never echo the line itself: it holds a secret
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Line {} is not KEY=VALUE", number + 1))?;
+
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!("Line {} has an invalid variable name", number + 1);
+        }
+
+        secrets.push((key.to_string(), unquote(value.trim()).to_string()));
+    }
+
+    Ok(secrets)
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+#[cfg(unix)]
+fn warn_if_shared(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            tracing::warn!("⚠️  Secrets file {:?} is readable by other users (chmod 600 it)", path);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_shared(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_secrets() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("qwen.env");
+        fs::write(&path, "# qwen credentials\nexport QWEN_API_KEY=\"abc=123\"\n\nQWEN_REGION='us'\nRAW = plain \n").unwrap();
+
+        let secrets = load_secrets(&path).unwrap();
+        assert_eq!(secrets, vec![
+            ("QWEN_API_KEY".to_string(), "abc=123".to_string()),
+            ("QWEN_REGION".to_string(), "us".to_string()),
+            ("RAW".to_string(), "plain".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_secrets_line_does_not_leak_value() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("bad.env");
+        fs::write(&path, "OK=1\nsk-live-very-secret\n").unwrap();
+
+        let error = format!("{:#}", load_secrets(&path).unwrap_err());
+        assert!(error.contains("Line 2"));
+        assert!(!error.contains("sk-live"));
+    }
+}
@@ -0,0 +1,55 @@
+//! Typed task execution errors that need distinct handling by callers
+
+use crate::config::RetryableError;
+use crate::mcp::protocol::error_codes;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TaskError {
+    #[error("Task was cancelled")]
+    Cancelled,
+    #[error("Agent timed out after {}s", .0.as_secs())]
+    TimedOut(Duration),
+    #[error("Failed to spawn agent process")]
+    SpawnFailed,
+    #[error("Agent process exited with error: {0}")]
+    ProcessExit(String),
+    #[error("Malformed agent response: {0}")]
+    MalformedResponse(String),
+    #[error("Agent returned error: {0}")]
+    AgentError(String),
+}
+
+impl TaskError {
+    /// Find a `TaskError` anywhere in an `anyhow` error chain,
+    /// including one attached with `.context(...)`
+    pub fn find(error: &anyhow::Error) -> Option<&TaskError> {
+        error
+            .downcast_ref::<TaskError>()
+            .or_else(|| error.chain().find_map(|cause| cause.downcast_ref::<TaskError>()))
+    }
+
+    /// Retry class of the failure; `None` for errors that are never retried
+    pub fn retry_class(&self) -> Option<RetryableError> {
+        match self {
+            TaskError::Cancelled => None,
+            TaskError::TimedOut(_) => Some(RetryableError::Timeout),
+            TaskError::SpawnFailed => Some(RetryableError::SpawnFailed),
+            TaskError::ProcessExit(_) => Some(RetryableError::ProcessExit),
+            TaskError::MalformedResponse(_) => Some(RetryableError::MalformedResponse),
+            TaskError::AgentError(_) => Some(RetryableError::AgentError),
+        }
+    }
+
+    /// Convert a task failure into an MCP error, keeping dedicated codes
+    pub fn to_mcp_error(error: &anyhow::Error) -> pmcp::Error {
+        match Self::find(error) {
+            Some(TaskError::TimedOut(_)) => pmcp::Error::protocol(
+                pmcp::ErrorCode::other(error_codes::AGENT_TIMEOUT),
+                format!("{:#}", error),
+            ),
+            _ => pmcp::Error::internal(error.to_string()),
+        }
+    }
+}
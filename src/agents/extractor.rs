@@ -1,102 +1,261 @@
-use crate::config::{AgentConfig, RoutingConfig, RoutingTier};
-use crate::agents::{AgentRegistry, AgentRouter, register::{TaskInfo, TaskStatus}};
-use crate::mcp::{DelegateTaskArgs, DelegateTaskOutput};
+use crate::config::{AgentConfig, LoadBalancingStrategy, RetryPolicy, RoutingConfig};
+use crate::agents::{AgentRegistry, AgentRouter, register::{TaskAttempt, TaskInfo, TaskStatus}};
+use crate::agents::admission::AdmissionController;
+use crate::agents::environment;
+use crate::agents::error::TaskError;
+use crate::agents::library::PROMPT_AGENT_TYPE;
+use crate::agents::retry;
+use crate::agents::route_cache::RouteCache;
+use crate::agents::router::RouteExplanation;
+use crate::agents::strategy::{AgentLoad, AgentLoads};
+use crate::mcp::{resources, AgentSummary, DelegateTaskArgs, DelegateTaskOutput, DescribeAgentOutput, ListAgentsArgs, SkippedAgent};
 use crate::rate_limit::RateLimitTracker;
-use anyhow::{Result, Context, bail};
+use anyhow::{anyhow, Result, Context, bail};
+use chrono::Utc;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::process::{Command, ChildStdin, ChildStdout};
+use tokio::process::{Command, Child, ChildStdin, ChildStdout};
+use tokio_util::sync::CancellationToken;
 use tokio::io::{AsyncWriteExt, AsyncBufReadExt, BufReader};
 use serde_json::Value;
+use std::time::Duration;
+use tokio::time::Instant;
 use uuid::Uuid;
 
 // Conditional Import for bundled pmat
 #[cfg(feature = "bundle-pmat")]
 use pmat_core::run_context_analysis; // สมมติว่า pmat-core มีฟังก์ชันนี้
 
+/// How long a cancelled agent gets to exit after SIGTERM before it is killed
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Skip reason of an agent (or its runner) with `enabled = false`
+const DISABLED: &str = "disabled";
+
+/// Agent picked for a task and the agent that executes it. A library
+/// persona runs on its runner, so the runner's quota, concurrency cap,
+/// timeout and retry policy apply; any other agent runs as itself.
+#[derive(Debug, Clone)]
+struct TaskAgent {
+    agent: AgentConfig,
+    runtime: AgentConfig,
+}
+
+impl TaskAgent {
+    fn resolve(registry: &AgentRegistry, agent: &AgentConfig) -> Self {
+        Self {
+            agent: agent.clone(),
+            runtime: runtime_agent(registry, agent).clone(),
+        }
+    }
+}
+
+/// The runner of a library persona when it is registered, otherwise `agent`
+fn runtime_agent<'a>(registry: &'a AgentRegistry, agent: &'a AgentConfig) -> &'a AgentConfig {
+    agent.persona
+        .as_ref()
+        .and_then(|persona| persona.runner.as_deref())
+        .and_then(|runner| registry.get_agent(runner))
+        .unwrap_or(agent)
+}
+
+/// External stop conditions for a running task
+struct TaskControl {
+    cancel: CancellationToken,
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl TaskControl {
+    fn new(cancel: CancellationToken, timeout: Option<Duration>) -> Self {
+        Self {
+            cancel,
+            deadline: timeout.map(|timeout| (Instant::now() + timeout, timeout)),
+        }
+    }
+
+    /// Resolves with the reason once the task has to stop
+    async fn interrupted(&self) -> TaskError {
+        match self.deadline {
+            Some((deadline, timeout)) => tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => TaskError::Cancelled,
+                _ = tokio::time::sleep_until(deadline) => TaskError::TimedOut(timeout),
+            },
+            None => {
+                self.cancel.cancelled().await;
+                TaskError::Cancelled
+            }
+        }
+    }
+}
 
 pub struct AgentExecutor {
     agent_registry: Arc<RwLock<AgentRegistry>>,
     rate_limiter: Arc<RwLock<RateLimitTracker>>,
-    router: AgentRouter,
+    /// Swapped as a whole on config reload
+    router: Arc<std::sync::RwLock<Arc<AgentRouter>>>,
+    /// Cached rankings and sticky sessions, kept across reloads
+    route_cache: Arc<std::sync::Mutex<RouteCache>>,
+    admission: Arc<AdmissionController>,
 }
 
 impl AgentExecutor {
     pub fn new(
         agent_registry: Arc<RwLock<AgentRegistry>>,
         rate_limiter: Arc<RwLock<RateLimitTracker>>,
+        admission: Arc<AdmissionController>,
         routing_config: RoutingConfig,
+        strategy: LoadBalancingStrategy,
     ) -> Self {
-        let router = AgentRouter::new(routing_config);
+        let route_cache = Arc::new(std::sync::Mutex::new(RouteCache::new(&routing_config.cache)));
+        let router = Arc::new(std::sync::RwLock::new(Arc::new(
+            AgentRouter::new(routing_config, strategy)
+        )));
 
         Self {
             agent_registry,
             rate_limiter,
             router,
+            route_cache,
+            admission,
         }
     }
 
-    /// Delegate a task to an appropriate sub-agent using ACP
-    pub async fn delegate_task(&self, args: DelegateTaskArgs) -> pmcp::Result<DelegateTaskOutput> {
+    /// Delegate a task to an appropriate sub-agent using ACP. A synchronous
+    /// task is cancelled when `request_cancelled` fires, like `cancel_task` would.
+    pub async fn delegate_task(
+        &self,
+        args: DelegateTaskArgs,
+        request_cancelled: CancellationToken,
+    ) -> pmcp::Result<DelegateTaskOutput> {
         // Generate task ID
         let task_id = Uuid::new_v4().to_string();
 
-        // Select agent
+        // Rank candidate agents
         let registry = self.agent_registry.read().await;
 
-        let agent_config = if let Some(agent_id) = &args.agent_id {
-            registry.get_agent(agent_id)
-                .ok_or_else(|| pmcp::Error::validation(format!("Agent not found: {}", agent_id)))?
-                .clone()
+        let candidates: Vec<AgentConfig> = if let Some(agent_id) = &args.agent_id {
+            vec![
+                registry.get_agent(agent_id)
+                    .ok_or_else(|| pmcp::Error::validation(format!("Agent not found: {}", agent_id)))?
+                    .clone()
+            ]
         } else {
             // Auto-select based on task_type
             let all_agents = registry.get_agents_by_priority();
-            let agent_refs: Vec<&AgentConfig> = all_agents.iter().copied().collect();
+            let agent_refs: Vec<&AgentConfig> = all_agents.to_vec();
+            let router = self.router();
+
+            // Only the rule and BM25 matching is cached; the balancer and
+            // loads order the candidates afresh on every call
+            let cached = self.route_cache().route_match(&args.task_type, &args.prompt, args.context.as_ref());
+            let matched = match cached {
+                Some(matched) => {
+                    tracing::debug!("♻️  Reusing cached rule matches for task_type='{}'", args.task_type);
+                    matched
+                }
+                None => {
+                    let matched = router.match_task(&args.task_type, &args.prompt, args.context.as_ref(), &agent_refs);
+                    self.route_cache().store_route_match(
+                        &args.task_type,
+                        &args.prompt,
+                        args.context.as_ref(),
+                        matched.clone(),
+                    );
+                    matched
+                }
+            };
+
+            let loads = self.collect_loads(&registry, &agent_refs).await;
+            let mut ranked: Vec<AgentConfig> = router
+                .rank_matched(&args.task_type, &matched, &agent_refs, &loads)
+                .into_iter()
+                .cloned()
+                .collect();
+
+            // Follow-up tasks of a session stay with its agent while it is
+            // registered; without quota it is skipped like any other
+            let sticky = args.session_id
+                .as_ref()
+                .and_then(|session_id| self.route_cache().session_agent(session_id, &args.task_type));
+            if let Some(position) = sticky.and_then(|id| ranked.iter().position(|agent| agent.id == id)) {
+                let agent = ranked.remove(position);
+                tracing::debug!("📌 Session keeps task_type='{}' on '{}'", args.task_type, agent.id);
+                ranked.insert(0, agent);
+            }
 
-            self.router.select_agent(&args.task_type, &args.prompt, &agent_refs)
-                .map_err(|e| pmcp::Error::internal(e.to_string()))?
-                .clone()
+            ranked
         };
+        let candidates: Vec<TaskAgent> = candidates
+            .iter()
+            .map(|agent| TaskAgent::resolve(&registry, agent))
+            .collect();
+        drop(registry);
+
+        // Walk the candidates until one has quota
+        let (selected, skipped_agents) = self.claim_first_with_quota(&candidates).await;
+
+        let Some(task_agent) = selected else {
+            // Every candidate is exhausted: record the task as failed
+            let agent_id = candidates.first().map(|c| c.agent.id.clone()).unwrap_or_default();
+            let (disabled, limited): (Vec<&SkippedAgent>, Vec<&SkippedAgent>) = skipped_agents
+                .iter()
+                .partition(|s| s.reason.ends_with(DISABLED));
+            let ids = |skipped: &[&SkippedAgent]| {
+                skipped.iter().map(|s| s.agent_id.as_str()).collect::<Vec<_>>().join(", ")
+            };
+            let error = match (limited.is_empty(), disabled.is_empty()) {
+                (true, false) => format!("Agent(s) disabled: {}", ids(&disabled)),
+                (false, false) => format!("Rate limit exceeded for agent(s): {}; disabled: {}", ids(&limited), ids(&disabled)),
+                _ => format!("Rate limit exceeded for agent(s): {}", ids(&limited)),
+            };
 
-        let agent_id = agent_config.id.clone();
+            let mut registry = self.agent_registry.write().await;
+            registry.register_task(TaskInfo::new(task_id.clone(), agent_id, args.task_type.clone()));
+            registry.fail_task(&task_id, error.clone())
+                .map_err(|e| pmcp::Error::internal(e.to_string()))?;
+
+            return Err(pmcp::Error::internal(error));
+        };
+
+        let agent_id = task_agent.agent.id.clone();
+        if let Some(session_id) = &args.session_id {
+            self.route_cache().store_session_agent(session_id, &args.task_type, &agent_id);
+        }
+        if !skipped_agents.is_empty() {
+            tracing::info!(
+                "🔀 Task {} failed over to '{}' after skipping {} agent(s)",
+                task_id, agent_id, skipped_agents.len()
+            );
+        }
 
         // Register task
-        drop(registry);
         let mut registry = self.agent_registry.write().await;
-        registry.register_task(TaskInfo {
-            task_id: task_id.clone(),
-            agent_id: agent_id.clone(),
-            task_type: args.task_type.clone(),
-            status: TaskStatus::Pending,
-        });
+        let cancel = registry.register_task(TaskInfo::new(
+            task_id.clone(),
+            agent_id.clone(),
+            args.task_type.clone(),
+        ));
         drop(registry);
 
-        // --- ส่วนที่แก้ไข: อัปเดตการเรียกใช้ Rate Limiter ---
-        // Check rate limit
-        let mut rate_limiter = self.rate_limiter.write().await;
-        // เราส่ง agent_config.rate_limit เข้าไปด้วย
-        if !rate_limiter.check_and_increment(&agent_id, &agent_config.rate_limit).await {
-            return Err(pmcp::Error::internal(
-                format!("Rate limit exceeded for agent: {}", agent_id)
-            ));
-        }
-        drop(rate_limiter);
-
         // Execute task
         if args.background {
             // Spawn background task
             let executor = self.clone_for_background();
             let task_id_clone = task_id.clone();
-            let agent_config_clone = agent_config.clone();
+            let task_agent_clone = task_agent.clone();
             let prompt_clone = args.prompt.clone();
             let context_clone = args.context.clone();
+            let timeout_secs = args.timeout_secs;
 
             tokio::spawn(async move {
                 if let Err(e) = executor.execute_agent_task(
                     task_id_clone,
-                    agent_config_clone,
+                    task_agent_clone,
                     prompt_clone,
                     context_clone,
+                    timeout_secs,
+                    cancel,
                 ).await {
                     tracing::error!("Background task failed: {}", e);
                 }
@@ -107,61 +266,339 @@ impl AgentExecutor {
                 agent_id,
                 status: "pending".to_string(),
                 result: None,
+                skipped_agents,
             })
         } else {
-            // Execute synchronously
-            let result = self.execute_agent_task(
+            // Execute synchronously; the caller giving up cancels the task
+            let run = self.execute_agent_task(
                 task_id.clone(),
-                agent_config,
+                task_agent,
                 args.prompt,
                 args.context,
-            ).await.map_err(|e| pmcp::Error::internal(e.to_string()))?;
+                args.timeout_secs,
+                cancel.clone(),
+            );
+            tokio::pin!(run);
+            let result = tokio::select! {
+                result = &mut run => result,
+                _ = request_cancelled.cancelled() => {
+                    tracing::info!("🛑 Request for task {} cancelled", task_id);
+                    cancel.cancel();
+                    run.await
+                }
+            }.map_err(|e| TaskError::to_mcp_error(&e))?;
 
             Ok(DelegateTaskOutput {
                 task_id,
                 agent_id,
                 status: "completed".to_string(),
                 result: Some(result),
+                skipped_agents,
             })
         }
     }
 
-    /// Execute task on a specific agent using ACP protocol
+    /// Explain where `delegate_task` would send a task, without running it
+    /// or consuming quota
+    pub async fn explain_route(
+        &self,
+        task_type: &str,
+        prompt: &str,
+        context: Option<&Value>,
+    ) -> RouteExplanation {
+        let registry = self.agent_registry.read().await;
+        let agent_refs = registry.get_agents_by_priority();
+        let loads = self.collect_loads(&registry, &agent_refs).await;
+
+        let mut explanation = self.router().explain(task_type, prompt, context, &agent_refs, &loads);
+
+        let rate_limiter = self.rate_limiter.read().await;
+        for preferred in explanation.rules.iter_mut().flat_map(|rule| rule.preferred_agents.iter_mut()) {
+            if let Some(agent) = registry.get_agent(&preferred.agent_id) {
+                let runtime = runtime_agent(&registry, agent);
+                let (daily, minute) = rate_limiter.remaining(&runtime.id, &runtime.rate_limit);
+                preferred.remaining_per_day = Some(daily);
+                preferred.remaining_per_minute = Some(minute);
+            }
+        }
+
+        explanation
+    }
+
+    /// Agents passing the filters in `args`, highest priority first
+    pub async fn list_agents(&self, args: &ListAgentsArgs) -> Vec<AgentSummary> {
+        let registry = self.agent_registry.read().await;
+        let mut agents: Vec<&AgentConfig> = registry
+            .get_agents_by_priority()
+            .into_iter()
+            .filter(|agent| args.matches(agent))
+            .collect();
+        agents.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| a.id.cmp(&b.id)));
+
+        let mut rate_limiter = self.rate_limiter.write().await;
+        rate_limiter.refresh().await;
+
+        agents
+            .into_iter()
+            .map(|agent| {
+                let runtime = runtime_agent(&registry, agent);
+                AgentSummary::new(
+                    agent,
+                    registry.active_task_count_for(&agent.id),
+                    rate_limiter.remaining(&runtime.id, &runtime.rate_limit),
+                )
+            })
+            .collect()
+    }
+
+    /// Full config of one agent with its load and the rules that prefer it
+    pub async fn describe_agent(&self, agent_id: &str) -> Option<DescribeAgentOutput> {
+        let registry = self.agent_registry.read().await;
+        let agent = registry.get_agent(agent_id)?;
+
+        let mut rate_limiter = self.rate_limiter.write().await;
+        rate_limiter.refresh().await;
+        let runtime = runtime_agent(&registry, agent);
+        let summary = AgentSummary::new(
+            agent,
+            registry.active_task_count_for(&agent.id),
+            rate_limiter.remaining(&runtime.id, &runtime.rate_limit),
+        );
+
+        // Values may be API keys; the command line is shown as written, so
+        // `${VAR}` stays a reference instead of the secret it expanded to
+        let mut config = agent.clone();
+        for value in config.env.values_mut() {
+            *value = "<redacted>".to_string();
+        }
+        if let Some(written) = config.as_written.take() {
+            config.command = written.command;
+            config.args = written.args;
+        }
+
+        Some(DescribeAgentOutput {
+            agent: summary,
+            config,
+            resource: agent.persona.as_ref().map(|_| resources::agent_uri(&agent.id)),
+            routing_rules: self.router().rules_for_agent(&agent.id),
+        })
+    }
+
+    /// Router currently in use
+    fn router(&self) -> Arc<AgentRouter> {
+        self.router.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn route_cache(&self) -> std::sync::MutexGuard<'_, RouteCache> {
+        self.route_cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Install a router built from a reloaded config. Callers hold the
+    /// registry write lock so routing never sees a half-applied reload.
+    pub fn replace_router(&self, routing_config: RoutingConfig, strategy: LoadBalancingStrategy) {
+        self.route_cache().reconfigure(&routing_config.cache);
+        let router = Arc::new(AgentRouter::new(routing_config, strategy));
+        *self.router.write().unwrap_or_else(|e| e.into_inner()) = router;
+    }
+
+    /// Consume quota from the first enabled candidate whose runtime agent
+    /// has any left, recording every candidate passed over on the way
+    async fn claim_first_with_quota(
+        &self,
+        candidates: &[TaskAgent],
+    ) -> (Option<TaskAgent>, Vec<SkippedAgent>) {
+        let mut rate_limiter = self.rate_limiter.write().await;
+        let mut skipped = Vec::new();
+
+        for candidate in candidates {
+            // เราส่ง rate_limit ของ agent ที่รันจริงเข้าไปด้วย
+            let runtime = &candidate.runtime;
+            if !candidate.agent.enabled || !runtime.enabled {
+                let reason = if candidate.agent.enabled {
+                    format!("runner '{}' {}", runtime.id, DISABLED)
+                } else {
+                    DISABLED.to_string()
+                };
+                skipped.push(SkippedAgent {
+                    agent_id: candidate.agent.id.clone(),
+                    reason,
+                });
+                continue;
+            }
+
+            if rate_limiter.check_and_increment(&runtime.id, &runtime.rate_limit).await {
+                return (Some(candidate.clone()), skipped);
+            }
+
+            let reason = if runtime.id == candidate.agent.id {
+                "rate limit exceeded".to_string()
+            } else {
+                format!("rate limit exceeded on runner '{}'", runtime.id)
+            };
+            skipped.push(SkippedAgent {
+                agent_id: candidate.agent.id.clone(),
+                reason,
+            });
+        }
+
+        (None, skipped)
+    }
+
+    /// Snapshot active tasks and remaining quota for the load-balancing strategies
+    async fn collect_loads(&self, registry: &AgentRegistry, agents: &[&AgentConfig]) -> AgentLoads {
+        let mut rate_limiter = self.rate_limiter.write().await;
+        rate_limiter.refresh().await;
+
+        agents
+            .iter()
+            .map(|agent| {
+                let runtime = runtime_agent(registry, agent);
+                let load = AgentLoad {
+                    active_tasks: registry.active_task_count_for(&agent.id),
+                    has_quota: rate_limiter.has_quota(&runtime.id, &runtime.rate_limit),
+                };
+                (agent.id.clone(), load)
+            })
+            .collect()
+    }
+
+    /// Execute task on a specific agent using ACP protocol,
+    /// retrying transient failures according to the agent's retry policy
     async fn execute_agent_task(
         &self,
         task_id: String,
-        agent: AgentConfig,
+        task_agent: TaskAgent,
         prompt: String,
         context: Option<Value>,
+        timeout_secs: Option<u64>,
+        cancel: CancellationToken,
     ) -> Result<String> {
+        let agent = &task_agent.runtime;
+
+        // Per-call override wins over the agent's configured deadline
+        let timeout = timeout_secs.or(agent.timeout_secs).map(Duration::from_secs);
+
+        let policy = agent.retry.clone().unwrap_or(RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        });
+
+        let mut attempt = 1;
+        let result = loop {
+            let started_at = Utc::now();
+            let result = self.run_agent_task(
+                &task_id, &task_agent, &prompt, context.clone(), cancel.clone(), timeout,
+            ).await;
+
+            let backoff = match &result {
+                Err(e) if attempt < policy.max_attempts && retry::is_retryable(&policy, e) => {
+                    Some(retry::backoff_delay(&policy, attempt))
+                }
+                _ => None,
+            };
+
+            self.agent_registry.write().await.record_attempt(&task_id, TaskAttempt {
+                attempt,
+                started_at,
+                finished_at: Utc::now(),
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                backoff_ms: backoff.map(|delay| delay.as_millis() as u64),
+            })?;
+
+            let Some(delay) = backoff else { break result };
+
+            tracing::warn!(
+                "🔁 Task {} attempt {}/{} on agent {} failed, retrying in {:?}",
+                task_id, attempt, policy.max_attempts, agent.id, delay
+            );
+
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => break Err(TaskError::Cancelled.into()),
+                _ = tokio::time::sleep(delay) => {}
+            }
+
+            // Every attempt counts against the agent's quota
+            let mut rate_limiter = self.rate_limiter.write().await;
+            if !rate_limiter.check_and_increment(&agent.id, &agent.rate_limit).await {
+                break result.context(format!("Retry skipped: rate limit exceeded for agent {}", agent.id));
+            }
+            drop(rate_limiter);
+
+            attempt += 1;
+        };
+
+        // Store the final status together with the result or error
+        let mut registry = self.agent_registry.write().await;
+        match &result {
+            Ok(output) => registry.complete_task(&task_id, output.clone())?,
+            Err(e) if TaskError::find(e) == Some(&TaskError::Cancelled) => {
+                tracing::info!("🛑 Task {} cancelled", task_id);
+                registry.update_task_status(&task_id, TaskStatus::Cancelled)?
+            }
+            Err(e) => registry.fail_task(&task_id, format!("{:#}", e))?,
+        }
+
+        result
+    }
+
+    async fn run_agent_task(
+        &self,
+        task_id: &str,
+        task_agent: &TaskAgent,
+        prompt: &str,
+        context: Option<Value>,
+        cancel: CancellationToken,
+        timeout: Option<Duration>,
+    ) -> Result<String> {
+        let agent = &task_agent.agent;
+
+        // Wait for a free slot on the agent that runs; the task stays Pending while queued
+        let runtime = &task_agent.runtime;
+        tracing::debug!("⏳ Task {} waiting for an execution slot on agent {}", task_id, runtime.id);
+        let _permit = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return Err(TaskError::Cancelled.into()),
+            permit = self.admission.admit(runtime) => permit,
+        };
+
         // Update status to running
         let mut registry = self.agent_registry.write().await;
-        registry.update_task_status(&task_id, TaskStatus::Running)?;
+        registry.update_task_status(task_id, TaskStatus::Running)?;
         drop(registry);
 
         tracing::info!("Executing task {} on agent {}", task_id, agent.id);
 
-        let result = match agent.agent_type.as_str() {
-            "cli" => self.execute_cli_agent(&agent, &prompt, context).await,
-            "gemini-extension" => self.execute_gemini_extension(&agent, &prompt).await,
+        // The deadline starts once the task leaves the queue
+        let control = TaskControl::new(cancel, timeout);
+
+        match agent.agent_type.as_str() {
+            "cli" => self.execute_cli_agent(agent, prompt, context, &control, None).await,
+            PROMPT_AGENT_TYPE => self.execute_prompt_agent(agent, runtime, prompt, context, &control).await,
+            "gemini-extension" => {
+                Self::until_interrupted(&control, self.execute_gemini_extension(agent, prompt)).await
+            }
             "internal" => {
                 if agent.command.as_deref() == Some("pmat-internal") {
-                    self.execute_internal_pmat_agent(&prompt).await
+                    Self::until_interrupted(&control, self.execute_internal_pmat_agent(prompt)).await
                 } else {
-                    bail!("Unsupported internal agent: {:?}", agent.command)
+                    Err(anyhow!("Unsupported internal agent: {:?}", agent.command))
                 }
             },
-            _ => bail!("Unsupported agent type: {}", agent.agent_type),
-        };
-
-        // Update final status
-        let mut registry = self.agent_registry.write().await;
-        match &result {
-            Ok(_) => registry.update_task_status(&task_id, TaskStatus::Completed)?,
-            Err(_) => registry.update_task_status(&task_id, TaskStatus::Failed)?,
+            _ => Err(anyhow!("Unsupported agent type: {}", agent.agent_type)),
         }
+    }
 
-        result
+    /// Drive an in-process agent, abandoning it when the task is cancelled or times out
+    async fn until_interrupted<T>(
+        control: &TaskControl,
+        future: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        tokio::select! {
+            biased;
+            reason = control.interrupted() => Err(reason.into()),
+            result = future => result,
+        }
     }
 
     #[cfg(feature = "bundle-pmat")]
@@ -176,29 +613,57 @@ impl AgentExecutor {
         bail!("Internal PMAT agent called, but the 'bundle-pmat' feature is not enabled. Please compile with --features bundle-pmat or use the CLI version of pmat.")
     }
 
+    /// Run a library persona on its runner CLI agent, passing the persona as system prompt
+    async fn execute_prompt_agent(
+        &self,
+        agent: &AgentConfig,
+        runner: &AgentConfig,
+        prompt: &str,
+        context: Option<Value>,
+        control: &TaskControl,
+    ) -> Result<String> {
+        let persona = agent.persona.as_ref()
+            .context("Prompt agent has no persona")?;
+        let runner_id = persona.runner.as_deref()
+            .with_context(|| format!(
+                "No runner for prompt agent '{}' (set agent_library.runner)", agent.id
+            ))?;
+        // `runner` is the persona itself when its runner was not registered
+        if runner.id != runner_id {
+            bail!("Runner agent not found: {}", runner_id);
+        }
+
+        tracing::debug!("Running persona '{}' on agent {}", agent.id, runner.id);
+        self.execute_cli_agent(runner, prompt, context, control, Some(&persona.system_prompt)).await
+    }
+
     async fn execute_cli_agent(
         &self,
         agent: &AgentConfig,
         prompt: &str,
         context: Option<Value>,
+        control: &TaskControl,
+        system_prompt: Option<&str>,
     ) -> Result<String> {
         let command = agent.command.as_ref()
             .context("CLI agent requires command")?;
 
         tracing::debug!("Spawning process: {} {:?}", command, agent.args);
 
-        let mut child = Command::new(command)
-            .args(agent.args.as_deref().unwrap_or(&[]))
+        let mut cmd = Command::new(command);
+        cmd.args(agent.args.as_deref().unwrap_or(&[]))
             .stdin(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
-            .spawn()
-            .context("Failed to spawn agent process")?;
+            .kill_on_drop(true);
+        environment::apply(&mut cmd, agent).await?;
+
+        let mut child = cmd.spawn().context(TaskError::SpawnFailed)?;
 
         let stdin = child.stdin.take().context("Failed to get stdin")?;
         let stdout = child.stdout.take().context("Failed to get stdout")?;
 
-        let acp_request = serde_json::json!({
+        let mut acp_request = serde_json::json!({
             "jsonrpc": "2.0",
             "id": 1,
             "method": "context",
@@ -208,20 +673,61 @@ impl AgentExecutor {
                 "format": "llm-optimized"
             }
         });
+        if let Some(system_prompt) = system_prompt {
+            acp_request["params"]["system_prompt"] = Value::from(system_prompt);
+        }
 
         let request_line = serde_json::to_string(&acp_request)? + "\n";
-        self.write_to_agent(stdin, &request_line).await?;
 
-        let response = self.read_from_agent(stdout).await?;
+        let outcome = tokio::select! {
+            biased;
+            reason = control.interrupted() => Err(reason),
+            result = async {
+                self.write_to_agent(stdin, &request_line).await?;
+                let response = self.read_from_agent(stdout).await?;
+                let status = child.wait().await?;
+                Ok::<_, anyhow::Error>((response, status))
+            } => Ok(result),
+        };
+
+        let result = match outcome {
+            Ok(result) => result,
+            Err(reason) => {
+                tracing::warn!("Stopping agent {}: {}", agent.id, reason);
+                Self::terminate_child(&mut child).await;
+                return Err(reason.into());
+            }
+        };
 
-        let status = child.wait().await?;
+        let (response, status) = result?;
         if !status.success() {
-            bail!("Agent process exited with error: {}", status);
+            return Err(TaskError::ProcessExit(status.to_string()).into());
         }
 
         Ok(response)
     }
 
+    /// Ask the agent process to exit, then kill it after a grace period
+    async fn terminate_child(child: &mut Child) {
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            // SAFETY: `pid` is our own child, which has not been reaped yet
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+
+            if tokio::time::timeout(TERMINATE_GRACE_PERIOD, child.wait()).await.is_ok() {
+                return;
+            }
+
+            tracing::warn!("Agent process {} ignored SIGTERM, killing", pid);
+        }
+
+        if let Err(e) = child.kill().await {
+            tracing::warn!("Failed to kill agent process: {}", e);
+        }
+    }
+
     async fn write_to_agent(&self, mut stdin: ChildStdin, data: &str) -> Result<()> {
         stdin.write_all(data.as_bytes()).await?;
         stdin.flush().await?;
@@ -235,7 +741,7 @@ impl AgentExecutor {
         reader.read_line(&mut line).await?;
 
         let response: Value = serde_json::from_str(&line)
-            .context("Failed to parse agent response")?;
+            .map_err(|e| TaskError::MalformedResponse(e.to_string()))?;
 
         if let Some(result) = response.get("result") {
             if result.is_string() {
@@ -244,16 +750,16 @@ impl AgentExecutor {
                 Ok(result.to_string())
             }
         } else if let Some(error) = response.get("error") {
-            bail!("Agent returned error: {}", error);
+            Err(TaskError::AgentError(error.to_string()).into())
         } else {
-            bail!("Invalid JSON-RPC response");
+            Err(TaskError::MalformedResponse("Invalid JSON-RPC response".to_string()).into())
         }
     }
 
     async fn execute_gemini_extension(
         &self,
         agent: &AgentConfig,
-        prompt: &str,
+        _prompt: &str,
     ) -> Result<String> {
         let extension_name = agent.extension_name.as_ref()
             .context("Extension agent requires extension_name")?;
@@ -266,7 +772,467 @@ impl AgentExecutor {
         Self {
             agent_registry: self.agent_registry.clone(),
             rate_limiter: self.rate_limiter.clone(),
-            router: AgentRouter::new(RoutingConfig { rules: vec![], tier: RoutingTier::Default }),
+            router: self.router.clone(),
+            route_cache: self.route_cache.clone(),
+            admission: self.admission.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LaunchTemplate, RateLimit, RateLimitingConfig, RoutingCacheConfig, RoutingRule};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn create_executor(temp: &TempDir, agents: Vec<AgentConfig>) -> AgentExecutor {
+        let rate_limiting = RateLimitingConfig {
+            strategy: LoadBalancingStrategy::default(),
+            track_usage: true,
+            usage_db_path: temp.path().join("usage.db").to_string_lossy().into_owned(),
+        };
+
+        AgentExecutor::new(
+            Arc::new(RwLock::new(AgentRegistry::new(agents))),
+            Arc::new(RwLock::new(RateLimitTracker::new(rate_limiting))),
+            Arc::new(AdmissionController::new(5)),
+            RoutingConfig::default(),
+            LoadBalancingStrategy::default(),
+        )
+    }
+
+    fn shell_agent(id: &str, script: &str) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: "cli".to_string(),
+            command: Some("sh".to_string()),
+            args: Some(vec!["-c".to_string(), script.to_string()]),
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn task_args(agent_id: &str, background: bool) -> DelegateTaskArgs {
+        DelegateTaskArgs {
+            task_type: "test".to_string(),
+            prompt: "hello".to_string(),
+            agent_id: Some(agent_id.to_string()),
+            background,
+            context: None,
+            timeout_secs: None,
+            session_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_sticks_to_agent() {
+        let temp = TempDir::new().unwrap();
+        let reply = r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":"ok"}'"#;
+        let mut preferred = shell_agent("preferred", reply);
+        preferred.priority = 90;
+        let executor = create_executor(&temp, vec![preferred, shell_agent("other", reply)]);
+
+        let auto = |session_id: Option<&str>| DelegateTaskArgs {
+            agent_id: None,
+            session_id: session_id.map(str::to_string),
+            ..task_args("", false)
+        };
+
+        // The session's first task went to "other" explicitly
+        let first = DelegateTaskArgs { session_id: Some("s1".to_string()), ..task_args("other", false) };
+        assert_eq!(executor.delegate_task(first, CancellationToken::new()).await.unwrap().agent_id, "other");
+
+        // Follow-ups stay there; other sessions route by priority
+        assert_eq!(executor.delegate_task(auto(Some("s1")), CancellationToken::new()).await.unwrap().agent_id, "other");
+        assert_eq!(executor.delegate_task(auto(Some("s2")), CancellationToken::new()).await.unwrap().agent_id, "preferred");
+        assert_eq!(executor.delegate_task(auto(None), CancellationToken::new()).await.unwrap().agent_id, "preferred");
+
+        // An unregistered session agent is no longer used
+        let remaining = executor.agent_registry.read().await.get_agent("preferred").cloned().unwrap();
+        executor.agent_registry.write().await.replace_agents(vec![remaining]);
+        assert_eq!(executor.delegate_task(auto(Some("s1")), CancellationToken::new()).await.unwrap().agent_id, "preferred");
+    }
+
+    #[tokio::test]
+    async fn test_cached_route_still_rotates() {
+        let temp = TempDir::new().unwrap();
+        let reply = r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":"ok"}'"#;
+        let executor = create_executor(&temp, vec![shell_agent("a", reply), shell_agent("b", reply)]);
+        executor.replace_router(
+            RoutingConfig {
+                rules: vec![RoutingRule {
+                    task_type: "test".to_string(),
+                    preferred_agents: vec!["a".to_string(), "b".to_string()],
+                    ..Default::default()
+                }],
+                cache: RoutingCacheConfig { enabled: true, ..Default::default() },
+                ..Default::default()
+            },
+            LoadBalancingStrategy::RoundRobin,
+        );
+
+        let auto = || DelegateTaskArgs { agent_id: None, ..task_args("", false) };
+        let mut picked = Vec::new();
+        for _ in 0..4 {
+            picked.push(executor.delegate_task(auto(), CancellationToken::new()).await.unwrap().agent_id);
+        }
+        // The second request hits the cache but round-robin keeps rotating
+        assert_eq!(picked, vec!["a", "b", "a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_agents_are_skipped() {
+        let temp = TempDir::new().unwrap();
+        let reply = r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":"ok"}'"#;
+        let mut off = shell_agent("off", reply);
+        off.enabled = false;
+        off.priority = 90;
+        let executor = create_executor(&temp, vec![off, shell_agent("on", reply)]);
+        executor.replace_router(
+            RoutingConfig {
+                rules: vec![RoutingRule {
+                    task_type: "test".to_string(),
+                    preferred_agents: vec!["off".to_string()],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            LoadBalancingStrategy::RoundRobin,
+        );
+
+        // The rule's only agent is off, so the priority fallback takes the task
+        let output = executor.delegate_task(DelegateTaskArgs { agent_id: None, ..task_args("", false) }, CancellationToken::new()).await.unwrap();
+        assert_eq!(output.agent_id, "on");
+        assert_eq!(output.skipped_agents.len(), 1);
+        assert_eq!(output.skipped_agents[0].agent_id, "off");
+        assert_eq!(output.skipped_agents[0].reason, "disabled");
+
+        // Naming it explicitly does not reach it either
+        let error = executor.delegate_task(task_args("off", false), CancellationToken::new()).await.unwrap_err();
+        assert!(error.to_string().contains("Agent(s) disabled: off"), "{}", error);
+        assert_eq!(executor.rate_limiter.read().await.get_usage("off"), None);
+    }
+
+    #[tokio::test]
+    async fn test_background_result_is_stored() {
+        let temp = TempDir::new().unwrap();
+        let executor = create_executor(&temp, vec![shell_agent(
+            "echo",
+            r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":"pong"}'"#,
+        )]);
+
+        let output = executor.delegate_task(task_args("echo", true), CancellationToken::new()).await.unwrap();
+
+        let mut task = None;
+        for _ in 0..100 {
+            let registry = executor.agent_registry.read().await;
+            let current = registry.get_task(&output.task_id).unwrap().clone();
+            if current.status.is_finished() {
+                task = Some(current);
+                break;
+            }
+            drop(registry);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let task = task.expect("background task should finish");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.result.as_deref(), Some("pong"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancel_terminates_agent_process() {
+        let temp = TempDir::new().unwrap();
+        let executor = Arc::new(create_executor(&temp, vec![shell_agent("sleeper", "sleep 30")]));
+
+        let running = {
+            let executor = executor.clone();
+            tokio::spawn(async move { executor.delegate_task(task_args("sleeper", false), CancellationToken::new()).await })
+        };
+
+        // Wait for the task to start, then cancel it
+        let task_id = loop {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let registry = executor.agent_registry.read().await;
+            let running = registry
+                .tasks()
+                .find(|t| t.status == TaskStatus::Running)
+                .map(|t| t.task_id.clone());
+            if let Some(task_id) = running {
+                break task_id;
+            }
+        };
+        executor.agent_registry.write().await.cancel_task(&task_id).unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(3), running)
+            .await
+            .expect("cancelled task should stop promptly")
+            .unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("cancelled"));
+        let registry = executor.agent_registry.read().await;
+        assert_eq!(registry.get_task(&task_id).unwrap().status, TaskStatus::Cancelled);
+        assert_eq!(executor.admission.running(), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_background_task_cancelled_before_start_never_runs() {
+        let temp = TempDir::new().unwrap();
+        let marker = temp.path().join("started");
+        let executor = create_executor(&temp, vec![shell_agent(
+            "marker",
+            &format!(r#"touch '{}'; read line; echo '{{"jsonrpc":"2.0","id":1,"result":"ran"}}'"#, marker.display()),
+        )]);
+
+        // The single-threaded test runtime has not polled the spawned task yet
+        let output = executor.delegate_task(task_args("marker", true), CancellationToken::new()).await.unwrap();
+        executor.agent_registry.write().await.cancel_task(&output.task_id).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!marker.exists(), "a cancelled task must not start its agent");
+        let registry = executor.agent_registry.read().await;
+        let task = registry.get_task(&output.task_id).unwrap();
+        assert_eq!(task.status, TaskStatus::Cancelled);
+        assert!(task.started_at.is_none());
+        assert_eq!(executor.admission.running(), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_agent_and_fails_task() {
+        let temp = TempDir::new().unwrap();
+        let mut agent = shell_agent("slow", "sleep 30");
+        agent.timeout_secs = Some(30);
+        let executor = create_executor(&temp, vec![agent]);
+
+        // The per-call override is shorter than the agent default
+        let mut args = task_args("slow", false);
+        args.timeout_secs = Some(1);
+
+        let error = tokio::time::timeout(Duration::from_secs(5), executor.delegate_task(args, CancellationToken::new()))
+            .await
+            .expect("timed out task should stop promptly")
+            .unwrap_err();
+
+        assert_eq!(
+            error.error_code(),
+            Some(pmcp::ErrorCode::other(crate::mcp::protocol::error_codes::AGENT_TIMEOUT))
+        );
+
+        let registry = executor.agent_registry.read().await;
+        let task = registry.tasks().next().unwrap();
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert!(task.error.as_deref().unwrap().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_transient_failure_is_retried() {
+        let temp = TempDir::new().unwrap();
+        let marker = temp.path().join("crashed-once");
+
+        // Crash without answering on the first run, answer on the second
+        let mut agent = shell_agent("flaky", &format!(
+            r#"if [ -f '{0}' ]; then read line; echo '{{"jsonrpc":"2.0","id":1,"result":"ok"}}'; else touch '{0}'; exit 1; fi"#,
+            marker.display()
+        ));
+        agent.retry = Some(RetryPolicy {
+            initial_backoff_ms: 10,
+            ..Default::default()
+        });
+        let executor = create_executor(&temp, vec![agent]);
+
+        let output = executor.delegate_task(task_args("flaky", false), CancellationToken::new()).await.unwrap();
+        assert_eq!(output.result.as_deref(), Some("ok"));
+
+        let registry = executor.agent_registry.read().await;
+        let task = registry.get_task(&output.task_id).unwrap();
+        assert_eq!(task.attempts.len(), 2);
+        assert!(task.attempts[0].error.as_deref().unwrap().contains("Malformed agent response"));
+        assert!(task.attempts[0].backoff_ms.is_some());
+        assert_eq!(task.attempts[1].error, None);
+
+        // Both attempts were charged to the agent
+        let (requests_today, _) = executor.rate_limiter.read().await.get_usage("flaky").unwrap();
+        assert_eq!(requests_today, 2);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_failure_is_not_retried() {
+        let temp = TempDir::new().unwrap();
+        let mut agent = shell_agent(
+            "refuses",
+            r#"read line; echo '{"jsonrpc":"2.0","id":1,"error":{"code":-1,"message":"no"}}'"#,
+        );
+        agent.retry = Some(RetryPolicy {
+            initial_backoff_ms: 10,
+            ..Default::default()
+        });
+        let executor = create_executor(&temp, vec![agent]);
+
+        let error = executor.delegate_task(task_args("refuses", false), CancellationToken::new()).await.unwrap_err();
+        assert!(error.to_string().contains("Agent returned error"));
+
+        let registry = executor.agent_registry.read().await;
+        let task = registry.tasks().next().unwrap();
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.attempts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_agent_runs_on_runner_with_persona() {
+        let temp = TempDir::new().unwrap();
+        let runner = shell_agent(
+            "runner",
+            r#"read line; case "$line" in *'"system_prompt":"Talk like a pirate."'*) echo '{"jsonrpc":"2.0","id":1,"result":"arr"}';; esac"#,
+        );
+        let pirate = AgentConfig {
+            id: "pirate".to_string(),
+            name: "Pirate".to_string(),
+            agent_type: PROMPT_AGENT_TYPE.to_string(),
+            enabled: true,
+            persona: Some(crate::config::AgentPersona {
+                system_prompt: "Talk like a pirate.".to_string(),
+                runner: Some("runner".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let executor = create_executor(&temp, vec![runner, pirate]);
+
+        let output = executor.delegate_task(task_args("pirate", false), CancellationToken::new()).await.unwrap();
+        assert_eq!(output.agent_id, "pirate");
+        assert_eq!(output.result.as_deref(), Some("arr"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_persona_is_charged_to_its_runner() {
+        let temp = TempDir::new().unwrap();
+        let mut runner = shell_agent("runner", r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":"ok"}'"#);
+        runner.rate_limit = RateLimit { requests_per_minute: 10, requests_per_day: 1 };
+        let mut slow_runner = shell_agent("slow-runner", "sleep 30");
+        slow_runner.timeout_secs = Some(1);
+        let persona = |id: &str, runner: &str| AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: PROMPT_AGENT_TYPE.to_string(),
+            enabled: true,
+            persona: Some(crate::config::AgentPersona {
+                system_prompt: "Be brief.".to_string(),
+                runner: Some(runner.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let executor = create_executor(
+            &temp,
+            vec![runner, slow_runner, persona("brief", "runner"), persona("slowpoke", "slow-runner")],
+        );
+
+        // The runner's daily quota of one covers the persona's first task only
+        executor.delegate_task(task_args("brief", false), CancellationToken::new()).await.unwrap();
+        let error = executor.delegate_task(task_args("brief", false), CancellationToken::new()).await.unwrap_err();
+        assert!(error.to_string().contains("Rate limit exceeded"), "{}", error);
+        let rate_limiter = executor.rate_limiter.read().await;
+        assert_eq!(rate_limiter.get_usage("runner"), Some((1, 1)));
+        assert_eq!(rate_limiter.get_usage("brief"), None);
+        drop(rate_limiter);
+
+        // The runner's timeout applies
+        let error = tokio::time::timeout(Duration::from_secs(5), executor.delegate_task(task_args("slowpoke", false), CancellationToken::new()))
+            .await
+            .expect("the runner's timeout should stop the persona")
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_agent_environment_and_cwd() {
+        let temp = TempDir::new().unwrap();
+        let workdir = temp.path().join("project");
+        std::fs::create_dir(&workdir).unwrap();
+        let secrets = temp.path().join("agent.env");
+        std::fs::write(&secrets, "TOKEN=from-file\nMODE=secret-wins\n").unwrap();
+
+        let mut agent = shell_agent(
+            "envy",
+            r#"read line; echo "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"$MODE $TOKEN ${HOME:-no-home} $(pwd)\"}""#,
+        );
+        agent.clear_env = true;
+        agent.env = HashMap::from([("MODE".to_string(), "from-env".to_string())]);
+        agent.secrets_from_file = Some(secrets.to_string_lossy().into_owned());
+        agent.cwd = Some(workdir.to_string_lossy().into_owned());
+        let executor = create_executor(&temp, vec![agent]);
+
+        let output = executor.delegate_task(task_args("envy", false), CancellationToken::new()).await.unwrap();
+        let workdir = workdir.canonicalize().unwrap();
+        assert_eq!(
+            output.result.unwrap(),
+            format!("secret-wins from-file no-home {}", workdir.display())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_and_describe_agents() {
+        let temp = TempDir::new().unwrap();
+        let mut coder = shell_agent("coder", "true");
+        coder.capabilities = vec!["code-generation".to_string()];
+        coder.priority = 50;
+        coder.env = HashMap::from([("API_KEY".to_string(), "sk-secret".to_string())]);
+        // As `Config::load` leaves it for `args = ["--token", "${API_TOKEN}"]`
+        coder.args = Some(vec!["--token".to_string(), "sk-secret".to_string()]);
+        coder.as_written = Some(LaunchTemplate {
+            command: Some("sh".to_string()),
+            args: Some(vec!["--token".to_string(), "${API_TOKEN}".to_string()]),
+        });
+        let mut writer = shell_agent("writer", "true");
+        writer.agent_type = PROMPT_AGENT_TYPE.to_string();
+        writer.category = Some("creative".to_string());
+        writer.capabilities = vec!["writing".to_string()];
+        writer.persona = Some(Default::default());
+        let mut off = shell_agent("off", "true");
+        off.enabled = false;
+        let executor = create_executor(&temp, vec![writer, off, coder]);
+        assert!(executor.rate_limiter.write().await.check_and_increment("coder", &RateLimit::default()).await);
+
+        let ids = |agents: Vec<AgentSummary>| agents.into_iter().map(|a| a.id).collect::<Vec<_>>();
+        assert_eq!(ids(executor.list_agents(&ListAgentsArgs::default()).await), vec!["coder", "writer"]);
+        let all = ListAgentsArgs { include_disabled: true, ..Default::default() };
+        assert_eq!(ids(executor.list_agents(&all).await), vec!["coder", "off", "writer"]);
+        let by_capability = ListAgentsArgs { capability: Some("Writing".to_string()), ..Default::default() };
+        assert_eq!(ids(executor.list_agents(&by_capability).await), vec!["writer"]);
+        let by_type = ListAgentsArgs { agent_type: Some("cli".to_string()), ..Default::default() };
+        assert_eq!(ids(executor.list_agents(&by_type).await), vec!["coder"]);
+        let by_category = ListAgentsArgs { category: Some("creative".to_string()), ..Default::default() };
+        assert_eq!(ids(executor.list_agents(&by_category).await), vec!["writer"]);
+
+        let coder = executor.describe_agent("coder").await.unwrap();
+        let limit = RateLimit::default();
+        assert_eq!(coder.agent.remaining_per_day, limit.requests_per_day - 1);
+        assert_eq!(coder.config.env["API_KEY"], "<redacted>");
+        assert_eq!(coder.config.command.as_deref(), Some("sh"));
+        assert_eq!(coder.config.args, Some(vec!["--token".to_string(), "${API_TOKEN}".to_string()]));
+        assert!(!serde_json::to_string(&coder).unwrap().contains("sk-secret"));
+        assert_eq!(coder.resource, None);
+        assert_eq!(executor.describe_agent("writer").await.unwrap().resource.as_deref(), Some("agent://writer"));
+        assert!(executor.describe_agent("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_example_config_agents_are_listed() {
+        let temp = TempDir::new().unwrap();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("Config.example.toml");
+        let config = crate::config::Config::load_unvalidated(path).unwrap();
+        let executor = create_executor(&temp, config.agents);
+
+        let ids: Vec<String> = executor.list_agents(&ListAgentsArgs::default()).await.into_iter().map(|a| a.id).collect();
+        for id in ["pmat-architect-internal", "qwen-coder", "codex-helper", "jules-agent"] {
+            assert!(ids.iter().any(|listed| listed == id), "{} missing from {:?}", id, ids);
         }
     }
 }
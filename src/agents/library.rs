@@ -0,0 +1,498 @@
+//! Agent library loader: persona files with YAML frontmatter (`agents/*.md`),
+//! the `agents.json` index next to them, and the `custom/agents.json` overlay.
+//!
+//! Mirrors `scripts/agent_manager.py`, so both see the same set of agents.
+
+use crate::config::{expand_tilde, AgentConfig, AgentLibraryConfig, AgentOrigin, AgentPersona};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Agent type of library personas
+pub const PROMPT_AGENT_TYPE: &str = "prompt";
+
+const INDEX_FILE: &str = "agents.json";
+
+/// YAML frontmatter of a persona file
+#[derive(Debug, Default, Deserialize)]
+pub struct Frontmatter {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub tools: Option<ToolList>,
+    pub color: Option<String>,
+}
+
+/// `tools` comes either as a list of names or as a `name: enabled` map
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ToolList {
+    Names(Vec<String>),
+    Flags(BTreeMap<String, bool>),
+}
+
+impl ToolList {
+    /// Names of the enabled tools
+    pub fn enabled(self) -> Vec<String> {
+        match self {
+            ToolList::Names(names) => names,
+            ToolList::Flags(flags) => flags
+                .into_iter()
+                .filter_map(|(name, enabled)| enabled.then_some(name))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AgentIndex {
+    #[serde(default)]
+    agents: Vec<IndexEntry>,
+}
+
+/// One entry of `agents.json`
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    id: String,
+    name: Option<String>,
+    file: String,
+    category: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    use_cases: Vec<String>,
+}
+
+/// Split a persona file into its frontmatter and markdown body
+pub fn parse_persona(content: &str) -> Result<(Frontmatter, String)> {
+    let content = content.trim_start_matches('\u{feff}');
+    let mut lines = content.split_inclusive('\n');
+
+    match lines.next() {
+        Some(first) if first.trim_end() == "---" => {}
+        _ => return Ok((Frontmatter::default(), content.trim().to_string())),
+    }
+
+    let mut yaml = String::new();
+    let mut closed = false;
+    for line in lines.by_ref() {
+        if line.trim_end() == "---" {
+            closed = true;
+            break;
+        }
+        yaml.push_str(line);
+    }
+
+    if !closed {
+        bail!("Unterminated frontmatter");
+    }
+
+    let frontmatter = if yaml.trim().is_empty() {
+        Frontmatter::default()
+    } else {
+        let fields = parse_frontmatter(&yaml).context("Invalid frontmatter")?;
+        serde_json::from_value(Value::Object(fields)).context("Invalid frontmatter")?
+    };
+
+    let body: String = lines.collect();
+    Ok((frontmatter, body.trim().to_string()))
+}
+
+/// Parse the YAML subset persona files use: `key: scalar` lines, `|`/`>`
+/// block scalars, and one indented level of `- item` lists or `key: value`
+/// maps. Anything nested deeper is skipped, since no field we read needs it.
+fn parse_frontmatter(yaml: &str) -> Result<Map<String, Value>> {
+    let mut fields = Map::new();
+    // Key with an empty value, collecting the indented lines below it
+    let mut open: Option<(String, Option<usize>)> = None;
+    let mut lines = yaml.lines().enumerate().peekable();
+
+    while let Some((index, line)) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let item = trimmed
+            .strip_prefix('-')
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '));
+
+        if indent > 0 || item.is_some() {
+            let Some((key, child_indent)) = open.as_mut() else {
+                bail!("Unexpected indented line {}", index + 1);
+            };
+            if indent > *child_indent.get_or_insert(indent) {
+                continue;
+            }
+            let value = fields.get_mut(key.as_str()).expect("open key is inserted");
+            match (item, value) {
+                (Some(item), Value::Array(items)) => items.push(scalar(item)),
+                (Some(item), value @ Value::Null) => *value = Value::Array(vec![scalar(item)]),
+                (None, Value::Object(entries)) => {
+                    let (name, entry) = split_entry(trimmed, index)?;
+                    entries.insert(name, scalar(entry));
+                }
+                (None, value @ Value::Null) => {
+                    let (name, entry) = split_entry(trimmed, index)?;
+                    *value = Value::Object(Map::from_iter([(name, scalar(entry))]));
+                }
+                _ => bail!("Mixed list and map entries on line {}", index + 1),
+            }
+            continue;
+        }
+
+        let (key, value) = split_entry(trimmed, index)?;
+        open = None;
+        let value = if value.is_empty() {
+            open = Some((key.clone(), None));
+            Value::Null
+        } else if let Some(style) = value.strip_prefix(['|', '>']).map(|_| &value[..1]) {
+            let mut block = Vec::new();
+            while let Some((_, next)) =
+                lines.next_if(|(_, next)| next.trim().is_empty() || next.starts_with([' ', '\t']))
+            {
+                block.push(next.trim());
+            }
+            let separator = if style == "|" { "\n" } else { " " };
+            Value::String(block.join(separator).trim().to_string())
+        } else {
+            scalar(value)
+        };
+        fields.insert(key, value);
+    }
+
+    Ok(fields)
+}
+
+/// Split `key: value`, with the key unquoted and the value trimmed
+fn split_entry(line: &str, index: usize) -> Result<(String, &str)> {
+    let (key, value) = line
+        .split_once(':')
+        .with_context(|| format!("Expected `key: value` on line {}", index + 1))?;
+    let key = key.trim().trim_matches(['"', '\'']).to_string();
+    Ok((key, value.trim()))
+}
+
+/// Plain, quoted or `[a, b]` flow scalar
+fn scalar(raw: &str) -> Value {
+    let raw = raw.trim();
+    if let Some(inner) = raw.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')) {
+        return Value::String(inner.replace("''", "'"));
+    }
+    if raw.starts_with('"') {
+        // Double-quoted YAML escapes are a superset of JSON's
+        if let Ok(text) = serde_json::from_str::<String>(raw) {
+            return Value::String(text);
+        }
+    }
+    let raw = raw.split(" #").next().unwrap_or_default().trim();
+    if let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        let items = inner.split(',').filter(|item| !item.trim().is_empty());
+        return Value::Array(items.map(scalar).collect());
+    }
+    match raw {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+/// Load every library agent. Problems with single files are logged and skipped.
+pub fn load(config: &AgentLibraryConfig) -> Vec<AgentConfig> {
+    let builtin_dir = expand_tilde(&config.path);
+    let custom_dir = expand_tilde(&config.custom_path);
+
+    let mut agents = load_dir(&builtin_dir, AgentOrigin::BuiltIn, true);
+
+    // Custom agents replace built-in ones with the same ID
+    for agent in load_dir(&custom_dir, AgentOrigin::Custom, false) {
+        agents.retain(|a| a.id != agent.id);
+        agents.push(agent);
+    }
+
+    for agent in &mut agents {
+        if let Some(persona) = agent.persona.as_mut() {
+            persona.runner = config.runner.clone();
+        }
+    }
+
+    agents.sort_by(|a, b| a.id.cmp(&b.id));
+    agents
+}
+
+/// Paths whose changes alter the library: both directories (to notice added
+/// or removed files), their indexes and every persona file
+pub fn watched_paths(config: &AgentLibraryConfig) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for dir in [expand_tilde(&config.path), expand_tilde(&config.custom_path)] {
+        paths.push(dir.join(INDEX_FILE));
+        if let Ok(entries) = load_index(&dir) {
+            paths.extend(entries.iter().map(|entry| dir.join(&entry.file)));
+        }
+        paths.extend(persona_files(&dir));
+        paths.push(dir);
+    }
+
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    paths
+}
+
+/// Agents listed in `dir/agents.json`, plus (if `scan`) persona files it doesn't list
+fn load_dir(dir: &Path, origin: AgentOrigin, scan: bool) -> Vec<AgentConfig> {
+    if !dir.is_dir() {
+        tracing::debug!("Agent library directory {:?} not found, skipping", dir);
+        return Vec::new();
+    }
+
+    let mut agents = Vec::new();
+    let mut indexed_files = HashSet::new();
+
+    match load_index(dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = dir.join(&entry.file);
+                indexed_files.insert(path.clone());
+
+                match load_indexed_agent(entry, &path, origin) {
+                    Ok(agent) => agents.push(agent),
+                    Err(e) => tracing::warn!("⚠️  Skipping library agent {:?}: {:#}", path, e),
+                }
+            }
+        }
+        Err(e) => tracing::warn!("⚠️  Failed to load {:?}: {:#}", dir.join(INDEX_FILE), e),
+    }
+
+    if scan {
+        for path in persona_files(dir) {
+            if indexed_files.contains(&path) {
+                continue;
+            }
+
+            match load_persona_file(&path, origin) {
+                Ok(agent) if agents.iter().any(|a| a.id == agent.id) => {
+                    tracing::debug!("Persona {:?} duplicates indexed agent '{}'", path, agent.id);
+                }
+                Ok(agent) => agents.push(agent),
+                Err(e) => tracing::warn!("⚠️  Skipping library agent {:?}: {:#}", path, e),
+            }
+        }
+    }
+
+    agents
+}
+
+fn load_index(dir: &Path) -> Result<Vec<IndexEntry>> {
+    let path = dir.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let index: AgentIndex = serde_json::from_str(&content)?;
+    Ok(index.agents)
+}
+
+fn persona_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Index metadata wins over the persona file's own frontmatter
+fn load_indexed_agent(entry: IndexEntry, path: &Path, origin: AgentOrigin) -> Result<AgentConfig> {
+    let mut agent = load_persona_file(path, origin)?;
+
+    agent.id = entry.id;
+    if let Some(name) = entry.name {
+        agent.name = name;
+    }
+    if entry.category.is_some() {
+        agent.category = entry.category;
+    }
+    if entry.description.is_some() {
+        agent.description = entry.description;
+    }
+    agent.capabilities = agent.category.iter().cloned().collect();
+    if let Some(persona) = agent.persona.as_mut() {
+        persona.use_cases = entry.use_cases;
+    }
+
+    Ok(agent)
+}
+
+fn load_persona_file(path: &Path, origin: AgentOrigin) -> Result<AgentConfig> {
+    let content = fs::read_to_string(path)?;
+    let (frontmatter, body) = parse_persona(&content)?;
+
+    let id = match frontmatter.name {
+        Some(name) => name,
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("Persona file name is not valid UTF-8")?
+            .to_string(),
+    };
+
+    Ok(AgentConfig {
+        name: id.clone(),
+        id,
+        agent_type: PROMPT_AGENT_TYPE.to_string(),
+        capabilities: frontmatter.category.iter().cloned().collect(),
+        enabled: true,
+        description: frontmatter.description,
+        category: frontmatter.category,
+        persona: Some(AgentPersona {
+            system_prompt: body,
+            tools: frontmatter.tools.map(ToolList::enabled).unwrap_or_default(),
+            color: frontmatter.color,
+            use_cases: Vec::new(),
+            source: path.to_path_buf(),
+            origin,
+            runner: None,
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn library_config(builtin: &Path, custom: &Path) -> AgentLibraryConfig {
+        AgentLibraryConfig {
+            enabled: true,
+            path: builtin.to_string_lossy().into_owned(),
+            custom_path: custom.to_string_lossy().into_owned(),
+            runner: Some("gemini".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_tool_list_and_map() {
+        let (frontmatter, body) = parse_persona(
+            "---\nname: finder\ntools:\n- Glob\n- Grep\ncolor: Blue\n---\n\nYou find things.\n",
+        )
+        .unwrap();
+        assert_eq!(frontmatter.name.as_deref(), Some("finder"));
+        assert_eq!(frontmatter.tools.unwrap().enabled(), vec!["Glob", "Grep"]);
+        assert_eq!(frontmatter.color.as_deref(), Some("Blue"));
+        assert_eq!(body, "You find things.");
+
+        let (frontmatter, _) = parse_persona(
+            "---\nmode: subagent\ntools:\n  read: true\n  bash: false\n  grep: true\n---\nbody",
+        )
+        .unwrap();
+        assert_eq!(frontmatter.tools.unwrap().enabled(), vec!["grep", "read"]);
+    }
+
+    #[test]
+    fn test_parse_quoted_block_and_unknown_keys() {
+        let (frontmatter, _) = parse_persona(concat!(
+            "---\n",
+            "name: 'it''s: quoted'\n",
+            "description: |\n  Reviews code.\n  Then reports: findings.\n",
+            "category: \"ops\\tteam\" \n",
+            "color: null\n",
+            "temperature: 0.1\n",
+            "globs:\n  - '**/*'\n",
+            "model:\n  options:\n    - nested\n",
+            "tools: [Read, 'Grep']  # inline\n",
+            "---\nbody",
+        ))
+        .unwrap();
+        assert_eq!(frontmatter.name.as_deref(), Some("it's: quoted"));
+        assert_eq!(
+            frontmatter.description.as_deref(),
+            Some("Reviews code.\nThen reports: findings.")
+        );
+        assert_eq!(frontmatter.category.as_deref(), Some("ops\tteam"));
+        assert!(frontmatter.color.is_none());
+        assert_eq!(frontmatter.tools.unwrap().enabled(), vec!["Read", "Grep"]);
+
+        assert!(parse_persona("---\n  stray: indent\n---\n").is_err());
+        assert!(parse_persona("---\ntools:\n  - Grep\n  read: true\n---\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_without_frontmatter() {
+        let (frontmatter, body) = parse_persona("# Just markdown").unwrap();
+        assert!(frontmatter.name.is_none());
+        assert_eq!(body, "# Just markdown");
+
+        assert!(parse_persona("---\nname: broken\n").is_err());
+    }
+
+    #[test]
+    fn test_load_index_scan_and_custom_overlay() {
+        let temp = TempDir::new().unwrap();
+        let builtin = temp.path().join("agents");
+        let custom = temp.path().join("custom");
+        fs::create_dir_all(&builtin).unwrap();
+        fs::create_dir_all(&custom).unwrap();
+
+        fs::write(builtin.join("agents.json"), r#"{"agents": [
+            {"id": "architect", "name": "Architect", "file": "code-architect.md",
+             "category": "engineering", "use_cases": ["Design"]},
+            {"id": "ghost", "file": "missing.md"}
+        ]}"#).unwrap();
+        fs::write(builtin.join("code-architect.md"), "---\nname: architect\ncategory: utility\n---\nPlan.").unwrap();
+        fs::write(builtin.join("pirate.md"), "---\nname: pirate\ndescription: Arr\n---\nTalk like a pirate.").unwrap();
+
+        fs::write(custom.join("agents.json"), r#"{"agents": [
+            {"id": "pirate", "name": "Custom Pirate", "file": "my-pirate.md"}
+        ]}"#).unwrap();
+        fs::write(custom.join("my-pirate.md"), "Yo ho.").unwrap();
+
+        let agents = load(&library_config(&builtin, &custom));
+        let ids: Vec<&str> = agents.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["architect", "pirate"]);
+
+        let architect = &agents[0];
+        assert_eq!(architect.agent_type, PROMPT_AGENT_TYPE);
+        assert_eq!(architect.name, "Architect");
+        assert_eq!(architect.category.as_deref(), Some("engineering"));
+        assert_eq!(architect.capabilities, vec!["engineering"]);
+        let persona = architect.persona.as_ref().unwrap();
+        assert_eq!(persona.system_prompt, "Plan.");
+        assert_eq!(persona.use_cases, vec!["Design"]);
+        assert_eq!(persona.runner.as_deref(), Some("gemini"));
+
+        let pirate = &agents[1];
+        assert_eq!(pirate.name, "Custom Pirate");
+        let persona = pirate.persona.as_ref().unwrap();
+        assert_eq!(persona.origin, AgentOrigin::Custom);
+        assert_eq!(persona.system_prompt, "Yo ho.");
+
+        let watched = watched_paths(&library_config(&builtin, &custom));
+        assert!(watched.contains(&builtin));
+        assert!(watched.contains(&builtin.join("missing.md")));
+        assert!(watched.contains(&builtin.join("pirate.md")));
+        assert!(watched.contains(&custom.join("my-pirate.md")));
+        assert_eq!(watched.iter().filter(|path| **path == builtin.join("code-architect.md")).count(), 1);
+    }
+
+    #[test]
+    fn test_load_bundled_library() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let agents = load(&library_config(&root.join("agents"), &root.join("custom")));
+
+        assert!(agents.len() > 20, "only {} agents loaded", agents.len());
+        let locator = agents.iter().find(|a| a.id == "codebase-locator").unwrap();
+        let persona = locator.persona.as_ref().unwrap();
+        assert!(persona.tools.contains(&"grep".to_string()));
+        assert!(!persona.tools.contains(&"bash".to_string()));
+        assert!(!persona.system_prompt.is_empty());
+    }
+}
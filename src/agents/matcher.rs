@@ -0,0 +1,308 @@
+//! Keyword and context matching for routing rules, compiled once per router
+
+use crate::config::{ContextCondition, KeywordMatchMode, RoutingRule};
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+
+/// Compile a rule pattern the way the router does (case-insensitive)
+pub fn compile_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// One keyword of a rule, ready to test against prompts
+#[derive(Debug, Clone)]
+pub enum KeywordMatcher {
+    Substring(String),
+    WholeWord(String),
+    Regex(Regex),
+    /// A pattern that failed to compile never matches
+    Invalid,
+}
+
+impl KeywordMatcher {
+    pub fn new(keyword: &str, mode: KeywordMatchMode) -> Self {
+        match mode {
+            KeywordMatchMode::Substring => Self::Substring(keyword.to_lowercase()),
+            KeywordMatchMode::WholeWord => Self::WholeWord(keyword.to_lowercase()),
+            KeywordMatchMode::Regex => match compile_regex(keyword) {
+                Ok(regex) => Self::Regex(regex),
+                Err(e) => {
+                    tracing::error!("❌ Invalid routing regex {:?}, it will never match: {}", keyword, e);
+                    Self::Invalid
+                }
+            },
+        }
+    }
+
+    pub fn is_match(&self, prompt: &Prompt) -> bool {
+        match self {
+            Self::Substring(keyword) => prompt.lower.contains(keyword.as_str()),
+            Self::WholeWord(keyword) => contains_word(&prompt.lower, keyword),
+            Self::Regex(regex) => regex.is_match(prompt.original),
+            Self::Invalid => false,
+        }
+    }
+}
+
+/// Compiled predicate on the task context
+#[derive(Debug, Clone)]
+pub struct ContextMatcher {
+    condition: ContextCondition,
+    glob: Option<Regex>,
+}
+
+impl ContextMatcher {
+    pub fn new(condition: &ContextCondition) -> Self {
+        Self {
+            glob: condition.glob.as_deref().map(glob_to_regex),
+            condition: condition.clone(),
+        }
+    }
+
+    pub fn is_match(&self, context: Option<&Value>) -> bool {
+        let Some(value) = context.and_then(|context| context.pointer(&self.condition.pointer)) else {
+            return false;
+        };
+
+        if let Some(expected) = &self.condition.equals {
+            return match (value.as_f64(), expected.as_f64()) {
+                (Some(actual), Some(expected)) => actual == expected,
+                _ => value == expected,
+            };
+        }
+        if let Some(glob) = &self.glob {
+            return value.as_str().is_some_and(|text| glob.is_match(text));
+        }
+        if let Some(gt) = self.condition.gt {
+            return value.as_f64().is_some_and(|number| number > gt);
+        }
+        if let Some(lt) = self.condition.lt {
+            return value.as_f64().is_some_and(|number| number < lt);
+        }
+        false
+    }
+}
+
+/// Anchored regex for a glob: `*` is any run of characters, `?` one character
+fn glob_to_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).expect("escaped glob is a valid regex")
+}
+
+/// A prompt with its lowercase form computed once for all rules
+pub struct Prompt<'a> {
+    original: &'a str,
+    lower: String,
+}
+
+impl<'a> Prompt<'a> {
+    pub fn new(original: &'a str) -> Self {
+        Self {
+            original,
+            lower: original.to_lowercase(),
+        }
+    }
+}
+
+/// Compiled keyword groups of a rule, keeping the source text for explanations
+#[derive(Debug, Clone, Default)]
+pub struct RuleMatcher {
+    keywords: Vec<(String, KeywordMatcher)>,
+    all_of: Vec<(String, KeywordMatcher)>,
+    any_of: Vec<(String, KeywordMatcher)>,
+    exclude: Vec<(String, KeywordMatcher)>,
+    context: Vec<ContextMatcher>,
+}
+
+/// Outcome of each keyword group against a prompt
+#[derive(Debug, Clone, Default)]
+pub struct KeywordMatch {
+    /// First of `keywords` found (`None` also when there are none)
+    pub keyword: Option<String>,
+    /// True when `keywords` is empty or one was found
+    pub keywords: bool,
+    /// First of `any_of` found
+    pub any_of: Option<String>,
+    pub any_of_matched: bool,
+    /// Entries of `all_of` that were not found
+    pub missing_all_of: Vec<String>,
+    /// First of `exclude_keywords` found
+    pub excluded_by: Option<String>,
+    /// Entries of `keywords`, `all_of` and `any_of` found, for scored routing
+    pub hits: usize,
+}
+
+impl KeywordMatch {
+    pub fn matched(&self) -> bool {
+        self.keywords && self.any_of_matched && self.missing_all_of.is_empty() && self.excluded_by.is_none()
+    }
+}
+
+impl RuleMatcher {
+    pub fn new(rule: &RoutingRule) -> Self {
+        let compile = |keywords: &[String]| -> Vec<(String, KeywordMatcher)> {
+            keywords
+                .iter()
+                .map(|keyword| (keyword.clone(), KeywordMatcher::new(keyword, rule.match_mode)))
+                .collect()
+        };
+
+        Self {
+            keywords: compile(&rule.keywords),
+            all_of: compile(&rule.all_of),
+            any_of: compile(&rule.any_of),
+            exclude: compile(&rule.exclude_keywords),
+            context: rule.context.iter().map(ContextMatcher::new).collect(),
+        }
+    }
+
+    /// First context condition that does not hold, as text
+    pub fn failed_context(&self, context: Option<&Value>) -> Option<String> {
+        self.context
+            .iter()
+            .find(|matcher| !matcher.is_match(context))
+            .map(|matcher| matcher.condition.to_string())
+    }
+
+    pub fn evaluate(&self, prompt: &Prompt) -> KeywordMatch {
+        let first_match = |group: &[(String, KeywordMatcher)]| {
+            group
+                .iter()
+                .find(|(_, matcher)| matcher.is_match(prompt))
+                .map(|(keyword, _)| keyword.clone())
+        };
+
+        let keyword = first_match(&self.keywords);
+        let any_of = first_match(&self.any_of);
+        let hits = self.keywords
+            .iter()
+            .chain(&self.all_of)
+            .chain(&self.any_of)
+            .filter(|(_, matcher)| matcher.is_match(prompt))
+            .count();
+
+        KeywordMatch {
+            keywords: self.keywords.is_empty() || keyword.is_some(),
+            keyword,
+            any_of_matched: self.any_of.is_empty() || any_of.is_some(),
+            any_of,
+            missing_all_of: self.all_of
+                .iter()
+                .filter(|(_, matcher)| !matcher.is_match(prompt))
+                .map(|(keyword, _)| keyword.clone())
+                .collect(),
+            excluded_by: first_match(&self.exclude),
+            hits,
+        }
+    }
+}
+
+/// `word` occurs in `text` with no letter, digit or `_` directly around it
+fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(mode: KeywordMatchMode) -> RoutingRule {
+        RoutingRule {
+            task_type: "code".to_string(),
+            match_mode: mode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let prompt = Prompt::new("Fetch the LATEST crate, then run cargo-test in C++");
+
+        let substring = KeywordMatcher::new("test", KeywordMatchMode::Substring);
+        let whole_word = KeywordMatcher::new("test", KeywordMatchMode::WholeWord);
+        assert!(substring.is_match(&prompt));
+        assert!(whole_word.is_match(&prompt)); // "cargo-test": '-' is a boundary
+        assert!(!whole_word.is_match(&Prompt::new("the latest tests")));
+        assert!(KeywordMatcher::new("c++", KeywordMatchMode::WholeWord).is_match(&prompt));
+
+        let regex = KeywordMatcher::new(r"\blatest\s+crate\b", KeywordMatchMode::Regex);
+        assert!(regex.is_match(&prompt));
+        assert!(!KeywordMatcher::new("(unclosed", KeywordMatchMode::Regex).is_match(&prompt));
+    }
+
+    #[test]
+    fn test_keyword_groups() {
+        let mut rust_not_wasm = rule(KeywordMatchMode::WholeWord);
+        rust_not_wasm.keywords = vec!["rust".to_string()];
+        rust_not_wasm.exclude_keywords = vec!["wasm".to_string()];
+        let matcher = RuleMatcher::new(&rust_not_wasm);
+
+        assert!(matcher.evaluate(&Prompt::new("Rust CLI")).matched());
+        let excluded = matcher.evaluate(&Prompt::new("rust to WASM"));
+        assert!(!excluded.matched());
+        assert_eq!(excluded.excluded_by.as_deref(), Some("wasm"));
+
+        let mut groups = rule(KeywordMatchMode::Substring);
+        groups.all_of = vec!["refactor".to_string(), "module".to_string()];
+        groups.any_of = vec!["rust".to_string(), "go".to_string()];
+        let matcher = RuleMatcher::new(&groups);
+
+        let full = matcher.evaluate(&Prompt::new("refactor this go module"));
+        assert!(full.matched());
+        assert_eq!(full.hits, 3);
+        let partial = matcher.evaluate(&Prompt::new("refactor this rust crate"));
+        assert!(!partial.matched());
+        assert_eq!(partial.missing_all_of, vec!["module".to_string()]);
+        assert!(!matcher.evaluate(&Prompt::new("refactor the python module")).matched());
+    }
+
+    #[test]
+    fn test_context_conditions() {
+        let condition = |pointer: &str| ContextCondition {
+            pointer: pointer.to_string(),
+            ..Default::default()
+        };
+        let context = serde_json::json!({
+            "language": "rust",
+            "repo": "github.com/acme/api",
+            "stats": { "file_count": 72 },
+        });
+
+        let mut rule = rule(KeywordMatchMode::Substring);
+        rule.context = vec![
+            ContextCondition { equals: Some(Value::from("rust")), ..condition("/language") },
+            ContextCondition { glob: Some("github.com/acme/*".to_string()), ..condition("/repo") },
+            ContextCondition { gt: Some(50.0), ..condition("/stats/file_count") },
+        ];
+        let matcher = RuleMatcher::new(&rule);
+        assert_eq!(matcher.failed_context(Some(&context)), None);
+        assert_eq!(matcher.failed_context(None).as_deref(), Some("/language == \"rust\""));
+
+        let mut small = context.clone();
+        small["stats"]["file_count"] = Value::from(12);
+        assert_eq!(matcher.failed_context(Some(&small)).as_deref(), Some("/stats/file_count > 50"));
+
+        // Integers and floats compare by value; globs only match strings
+        let equals_int = ContextMatcher::new(&ContextCondition { equals: Some(Value::from(72.0)), ..condition("/stats/file_count") });
+        assert!(equals_int.is_match(Some(&context)));
+        let glob_number = ContextMatcher::new(&ContextCondition { glob: Some("*".to_string()), ..condition("/stats/file_count") });
+        assert!(!glob_number.is_match(Some(&context)));
+    }
+}
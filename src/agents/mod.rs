@@ -1,9 +1,20 @@
+pub mod admission;
+pub mod environment;
+pub mod error;
 pub mod register;
+pub mod retry;
+pub mod route_cache;
 pub mod router;
+pub mod semantic;
+pub mod strategy;
 pub mod extractor;
+pub mod library;
+pub mod matcher;
+// Agent Creator is not wired into the MCP server yet
+#[allow(dead_code)]
 pub mod creator;
 
+pub use admission::AdmissionController;
 pub use register::AgentRegistry;
 pub use router::AgentRouter;
 pub use extractor::AgentExecutor;
-pub use creator::AgentCreator;
\ No newline at end of file
@@ -1,26 +1,89 @@
 use crate::config::AgentConfig;
 use std::collections::HashMap;
 use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+/// Finished tasks kept for `get_task_result` before the oldest are evicted
+const MAX_FINISHED_TASKS: usize = 1000;
 
 pub struct AgentRegistry {
     agents: HashMap<String, AgentConfig>,
     active_tasks: HashMap<String, TaskInfo>,
+    /// Cancellation token of every unfinished task
+    cancellations: HashMap<String, CancellationToken>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)] // เพิ่ม PartialEq, Eq เพื่อให้ง่ายต่อการ assert ในเทสต์
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)] // เพิ่ม PartialEq, Eq เพื่อให้ง่ายต่อการ assert ในเทสต์
 pub struct TaskInfo {
     pub task_id: String,
     pub agent_id: String,
     pub task_type: String,
     pub status: TaskStatus,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// One entry per execution attempt, oldest first
+    pub attempts: Vec<TaskAttempt>,
+}
+
+/// Outcome of a single execution attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct TaskAttempt {
+    /// 1-based attempt number
+    pub attempt: u32,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Failure of this attempt, if it failed
+    pub error: Option<String>,
+    /// Delay before the next attempt, if one was scheduled
+    pub backoff_ms: Option<u64>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)] // เพิ่ม PartialEq, Eq เพื่อให้ง่ายต่อการ assert ในเทสต์
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)] // เพิ่ม PartialEq, Eq เพื่อให้ง่ายต่อการ assert ในเทสต์
+#[serde(rename_all = "lowercase")]
 pub enum TaskStatus {
     Pending,
     Running,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl TaskStatus {
+    /// Whether the task has stopped running for good
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, TaskStatus::Running | TaskStatus::Pending)
+    }
+}
+
+impl TaskInfo {
+    /// New pending task
+    pub fn new(task_id: String, agent_id: String, task_type: String) -> Self {
+        Self {
+            task_id,
+            agent_id,
+            task_type,
+            status: TaskStatus::Pending,
+            result: None,
+            error: None,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Wall-clock run time in milliseconds, once the task has started
+    pub fn duration_ms(&self) -> Option<i64> {
+        let started = self.started_at?;
+        let finished = self.finished_at.unwrap_or_else(Utc::now);
+        Some(finished.signed_duration_since(started).num_milliseconds())
+    }
 }
 
 impl AgentRegistry {
@@ -33,15 +96,25 @@ impl AgentRegistry {
         Self {
             agents: agents_map,
             active_tasks: HashMap::new(),
+            cancellations: HashMap::new(),
         }
     }
 
+    /// Swap in a new set of agents, keeping every tracked task
+    pub fn replace_agents(&mut self, agents: Vec<AgentConfig>) {
+        self.agents = agents
+            .into_iter()
+            .map(|agent| (agent.id.clone(), agent))
+            .collect();
+    }
+
     /// Get agent by ID
     pub fn get_agent(&self, id: &str) -> Option<&AgentConfig> {
         self.agents.get(id)
     }
 
     /// Get agents by capability
+    #[cfg(test)]
     pub fn get_agents_by_capability(&self, capability: &str) -> Vec<&AgentConfig> {
         self.agents
             .values()
@@ -57,21 +130,106 @@ impl AgentRegistry {
     /// Get all agents sorted by priority (higher first)
     pub fn get_agents_by_priority(&self) -> Vec<&AgentConfig> {
         let mut agents: Vec<&AgentConfig> = self.agents.values().collect();
-        agents.sort_by(|a, b| b.priority.cmp(&a.priority));
+        agents.sort_by_key(|a| std::cmp::Reverse(a.priority));
         agents
     }
 
-    /// Register a new task
-    pub fn register_task(&mut self, task: TaskInfo) {
+    /// Register a new task. The returned token fires when the task is
+    /// cancelled; whoever runs the task must keep it from here on, since
+    /// the registry forgets it once the task finishes.
+    pub fn register_task(&mut self, task: TaskInfo) -> CancellationToken {
+        let token = CancellationToken::new();
+        if !task.status.is_finished() {
+            self.cancellations.insert(task.task_id.clone(), token.clone());
+        }
         self.active_tasks.insert(task.task_id.clone(), task);
+        self.evict_old_finished_tasks();
+        token
+    }
+
+    /// Cancel an unfinished task. The task is marked `Cancelled` right away;
+    /// whoever runs it observes the token and stops the agent.
+    pub fn cancel_task(&mut self, task_id: &str) -> Result<()> {
+        let status = self.active_tasks
+            .get(task_id)
+            .map(|task| task.status.clone())
+            .context("Task not found")?;
+
+        if status.is_finished() {
+            anyhow::bail!("Task {} already finished with status {:?}", task_id, status);
+        }
+
+        if let Some(token) = self.cancellations.get(task_id) {
+            token.cancel();
+        }
+
+        self.update_task_status(task_id, TaskStatus::Cancelled)
+    }
+
+    /// Get task by ID
+    pub fn get_task(&self, task_id: &str) -> Option<&TaskInfo> {
+        self.active_tasks.get(task_id)
+    }
+
+    /// Iterate over every tracked task
+    #[cfg(test)]
+    pub fn tasks(&self) -> impl Iterator<Item = &TaskInfo> {
+        self.active_tasks.values()
     }
 
     /// Update task status
     pub fn update_task_status(&mut self, task_id: &str, status: TaskStatus) -> Result<()> {
-        self.active_tasks
+        let task = self.active_tasks
             .get_mut(task_id)
-            .map(|task| task.status = status)
-            .context("Task not found")
+            .context("Task not found")?;
+
+        // A cancelled task keeps that status even if its agent finishes afterwards
+        if task.status == TaskStatus::Cancelled {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        if status == TaskStatus::Running && task.started_at.is_none() {
+            task.started_at = Some(now);
+        }
+        if status.is_finished() {
+            task.finished_at = Some(now);
+            self.cancellations.remove(task_id);
+        }
+        task.status = status;
+
+        Ok(())
+    }
+
+    /// Append an attempt to the task's history
+    pub fn record_attempt(&mut self, task_id: &str, attempt: TaskAttempt) -> Result<()> {
+        let task = self.active_tasks
+            .get_mut(task_id)
+            .context("Task not found")?;
+        task.attempts.push(attempt);
+        Ok(())
+    }
+
+    /// Mark a task completed and store its result
+    pub fn complete_task(&mut self, task_id: &str, result: String) -> Result<()> {
+        self.update_task_status(task_id, TaskStatus::Completed)?;
+        if let Some(task) = self.active_tasks.get_mut(task_id) {
+            if task.status == TaskStatus::Completed {
+                task.result = Some(result);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark a task failed and store its error
+    pub fn fail_task(&mut self, task_id: &str, error: String) -> Result<()> {
+        self.update_task_status(task_id, TaskStatus::Failed)?;
+        if let Some(task) = self.active_tasks.get_mut(task_id) {
+            if task.status == TaskStatus::Failed {
+                task.error = Some(error);
+            }
+        }
+        Ok(())
     }
 
     /// Get active task count
@@ -82,12 +240,44 @@ impl AgentRegistry {
             .count()
     }
 
+    /// Get active (pending or running) task count for one agent
+    pub fn active_task_count_for(&self, agent_id: &str) -> usize {
+        self.active_tasks
+            .values()
+            .filter(|task| {
+                task.agent_id == agent_id
+                    && matches!(task.status, TaskStatus::Running | TaskStatus::Pending)
+            })
+            .count()
+    }
+
     /// Remove completed/failed tasks (cleanup)
+    #[cfg(test)]
     pub fn cleanup_finished_tasks(&mut self) {
         self.active_tasks.retain(|_, task| {
             matches!(task.status, TaskStatus::Running | TaskStatus::Pending)
         });
     }
+
+    /// Drop the oldest finished tasks beyond `MAX_FINISHED_TASKS`
+    fn evict_old_finished_tasks(&mut self) {
+        let mut finished: Vec<(DateTime<Utc>, String)> = self.active_tasks
+            .values()
+            .filter(|task| task.status.is_finished())
+            .map(|task| (task.finished_at.unwrap_or(task.created_at), task.task_id.clone()))
+            .collect();
+
+        if finished.len() <= MAX_FINISHED_TASKS {
+            return;
+        }
+
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED_TASKS;
+        for (_, task_id) in finished.into_iter().take(excess) {
+            self.active_tasks.remove(&task_id);
+            self.cancellations.remove(&task_id);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -107,6 +297,8 @@ mod tests {
                 rate_limit: RateLimit::default(),
                 capabilities: vec!["cli-task".to_string()],
                 priority: 1,
+                enabled: true,
+                ..Default::default()
             },
             // --- ส่วนที่เพิ่มเข้ามา: เพิ่ม Internal Agent ในชุดข้อมูลเทสต์ ---
             AgentConfig {
@@ -119,6 +311,8 @@ mod tests {
                 rate_limit: RateLimit::default(),
                 capabilities: vec!["code-analysis".to_string()],
                 priority: 10, // ให้ priority สูงกว่า
+                enabled: true,
+                ..Default::default()
             },
         ]
     }
@@ -167,15 +361,16 @@ mod tests {
         let agents = create_test_agents();
         let mut registry = AgentRegistry::new(agents);
 
-        let task_info = TaskInfo {
-            task_id: "task-123".to_string(),
-            agent_id: "internal-pmat".to_string(),
-            task_type: "code-analysis".to_string(),
-            status: TaskStatus::Pending,
-        };
+        let task_info = TaskInfo::new(
+            "task-123".to_string(),
+            "internal-pmat".to_string(),
+            "code-analysis".to_string(),
+        );
 
         registry.register_task(task_info.clone());
         assert_eq!(registry.active_task_count(), 1);
+        assert_eq!(registry.active_task_count_for("internal-pmat"), 1);
+        assert_eq!(registry.active_task_count_for("cli-agent"), 0);
 
         registry.update_task_status("task-123", TaskStatus::Running).unwrap();
         let updated_task = registry.active_tasks.get("task-123").unwrap();
@@ -186,6 +381,66 @@ mod tests {
         assert_eq!(registry.active_task_count(), 0);
 
         registry.cleanup_finished_tasks();
-        assert!(registry.active_tasks.get("task-123").is_none());
+        assert!(!registry.active_tasks.contains_key("task-123"));
+    }
+
+    #[test]
+    fn test_task_results_are_kept() {
+        let mut registry = AgentRegistry::new(create_test_agents());
+
+        registry.register_task(TaskInfo::new(
+            "ok".to_string(),
+            "cli-agent".to_string(),
+            "cli-task".to_string(),
+        ));
+        registry.register_task(TaskInfo::new(
+            "bad".to_string(),
+            "cli-agent".to_string(),
+            "cli-task".to_string(),
+        ));
+
+        registry.update_task_status("ok", TaskStatus::Running).unwrap();
+        registry.complete_task("ok", "done".to_string()).unwrap();
+        registry.fail_task("bad", "boom".to_string()).unwrap();
+
+        let ok = registry.get_task("ok").unwrap();
+        assert_eq!(ok.status, TaskStatus::Completed);
+        assert_eq!(ok.result.as_deref(), Some("done"));
+        assert!(ok.started_at.is_some() && ok.finished_at.is_some());
+        assert!(ok.duration_ms().is_some());
+
+        let bad = registry.get_task("bad").unwrap();
+        assert_eq!(bad.status, TaskStatus::Failed);
+        assert_eq!(bad.error.as_deref(), Some("boom"));
+        assert!(bad.duration_ms().is_none());
+
+        assert!(registry.get_task("missing").is_none());
+    }
+
+    #[test]
+    fn test_cancel_task() {
+        let mut registry = AgentRegistry::new(create_test_agents());
+        let token = registry.register_task(TaskInfo::new(
+            "task-1".to_string(),
+            "cli-agent".to_string(),
+            "cli-task".to_string(),
+        ));
+        registry.update_task_status("task-1", TaskStatus::Running).unwrap();
+
+        registry.cancel_task("task-1").unwrap();
+
+        assert!(token.is_cancelled());
+        assert_eq!(registry.get_task("task-1").unwrap().status, TaskStatus::Cancelled);
+        assert_eq!(registry.active_task_count(), 0);
+
+        // A late result from the agent does not overwrite the cancellation
+        registry.complete_task("task-1", "late".to_string()).unwrap();
+        let task = registry.get_task("task-1").unwrap();
+        assert_eq!(task.status, TaskStatus::Cancelled);
+        assert!(task.result.is_none());
+
+        // Finished and unknown tasks cannot be cancelled
+        assert!(registry.cancel_task("task-1").is_err());
+        assert!(registry.cancel_task("missing").is_err());
     }
 }
@@ -0,0 +1,73 @@
+//! Retry decisions and backoff delays for `AgentConfig::retry`
+
+use crate::agents::error::TaskError;
+use crate::config::RetryPolicy;
+use rand::Rng;
+use std::time::Duration;
+
+/// Whether `error` belongs to a class the policy retries
+pub fn is_retryable(policy: &RetryPolicy, error: &anyhow::Error) -> bool {
+    TaskError::find(error)
+        .and_then(TaskError::retry_class)
+        .is_some_and(|class| policy.retry_on.contains(&class))
+}
+
+/// Delay before attempt `attempt + 1`, where `attempt` is the one that just failed.
+/// Exponential growth capped at `max_backoff_ms`, with "equal jitter": half of the
+/// delay is fixed and the other half random, so retries from many tasks spread out.
+pub fn backoff_delay(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(63) as i32;
+    let base = policy.initial_backoff_ms as f64 * policy.backoff_multiplier.powi(exponent);
+    let capped = base.min(policy.max_backoff_ms as f64) as u64;
+
+    let half = capped / 2;
+    let jitter = rand::rng().random_range(0..=capped - half);
+    Duration::from_millis(half + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RetryableError;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+            backoff_multiplier: 2.0,
+            ..Default::default()
+        };
+
+        for _ in 0..20 {
+            let first = backoff_delay(&policy, 1).as_millis();
+            assert!((50..=100).contains(&first), "{}", first);
+
+            let third = backoff_delay(&policy, 3).as_millis();
+            assert!((200..=400).contains(&third), "{}", third);
+
+            let late = backoff_delay(&policy, 30).as_millis();
+            assert!((500..=1000).contains(&late), "{}", late);
+        }
+    }
+
+    #[test]
+    fn test_retryable_classes() {
+        let policy = RetryPolicy {
+            retry_on: vec![RetryableError::MalformedResponse],
+            ..Default::default()
+        };
+
+        let malformed = anyhow::Error::from(TaskError::MalformedResponse("eof".into()));
+        assert!(is_retryable(&policy, &malformed));
+
+        // Attached as context, as execute_cli_agent does for spawn failures
+        let spawn = anyhow::anyhow!("No such file").context(TaskError::SpawnFailed);
+        assert!(!is_retryable(&policy, &spawn));
+        assert!(is_retryable(&RetryPolicy::default(), &spawn));
+
+        let cancelled = anyhow::Error::from(TaskError::Cancelled);
+        assert!(!is_retryable(&policy, &cancelled));
+        assert!(!is_retryable(&policy, &anyhow::anyhow!("Unsupported agent type: x")));
+    }
+}
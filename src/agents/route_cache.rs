@@ -0,0 +1,196 @@
+//! Routing decision cache and sticky sessions.
+//!
+//! Rule and BM25 matches are cached by task type, normalized prompt and
+//! context. Only the matching is reused: the load balancer and current loads
+//! still order the candidates on every request.
+//! Sessions remember the agent that last ran each task type, so follow-up
+//! tasks stay with it as long as it is registered and has quota.
+
+use crate::agents::router::RouteMatch;
+use crate::config::RoutingCacheConfig;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Bounded map whose entries expire `ttl` after insertion. When full, the
+/// least recently used entry is evicted.
+struct LruCache<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    capacity: usize,
+    ttl: Duration,
+    /// Increases on every access, to find the least recently used entry
+    clock: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    last_used: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            ttl,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        if now.duration_since(entry.inserted) >= self.ttl {
+            self.entries.remove(key);
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, now: Instant) {
+        self.clock += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.evict_least_recently_used();
+        }
+        self.entries.insert(key, Entry { value, inserted: now, last_used: self.clock });
+    }
+
+    /// Apply new limits, evicting down to the new capacity
+    fn resize(&mut self, capacity: usize, ttl: Duration) {
+        self.capacity = capacity.max(1);
+        self.ttl = ttl;
+        while self.entries.len() > self.capacity {
+            self.evict_least_recently_used();
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+pub struct RouteCache {
+    enabled: bool,
+    /// Rule and BM25 matches by request hash
+    decisions: LruCache<u64, RouteMatch>,
+    /// Agent ID by (session, task type)
+    sessions: LruCache<(String, String), String>,
+}
+
+impl RouteCache {
+    pub fn new(config: &RoutingCacheConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            decisions: LruCache::new(config.capacity, Duration::from_secs(config.ttl_secs)),
+            sessions: LruCache::new(config.capacity, Duration::from_secs(config.session_ttl_secs)),
+        }
+    }
+
+    /// Apply a reloaded config. Cached matches may come from the old rules
+    /// and are dropped; sessions are kept.
+    pub fn reconfigure(&mut self, config: &RoutingCacheConfig) {
+        self.enabled = config.enabled;
+        self.decisions = LruCache::new(config.capacity, Duration::from_secs(config.ttl_secs));
+        self.sessions.resize(config.capacity, Duration::from_secs(config.session_ttl_secs));
+    }
+
+    /// Cached match for a request, if the cache is enabled and it is fresh
+    pub fn route_match(&mut self, task_type: &str, prompt: &str, context: Option<&Value>) -> Option<RouteMatch> {
+        if !self.enabled {
+            return None;
+        }
+        self.decisions.get(&request_key(task_type, prompt, context), Instant::now())
+    }
+
+    pub fn store_route_match(&mut self, task_type: &str, prompt: &str, context: Option<&Value>, matched: RouteMatch) {
+        if self.enabled {
+            self.decisions.insert(request_key(task_type, prompt, context), matched, Instant::now());
+        }
+    }
+
+    /// Agent that last ran `task_type` in the session
+    pub fn session_agent(&mut self, session_id: &str, task_type: &str) -> Option<String> {
+        self.sessions.get(&(session_id.to_string(), task_type.to_string()), Instant::now())
+    }
+
+    pub fn store_session_agent(&mut self, session_id: &str, task_type: &str, agent_id: &str) {
+        self.sessions.insert(
+            (session_id.to_string(), task_type.to_string()),
+            agent_id.to_string(),
+            Instant::now(),
+        );
+    }
+}
+
+/// Hash of a request with the prompt normalized: case and runs of
+/// whitespace do not change the key
+fn request_key(task_type: &str, prompt: &str, context: Option<&Value>) -> u64 {
+    let normalized = prompt
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut hasher = DefaultHasher::new();
+    task_type.hash(&mut hasher);
+    normalized.hash(&mut hasher);
+    context.map(Value::to_string).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_and_ttl() {
+        let start = Instant::now();
+        let mut cache = LruCache::new(2, Duration::from_secs(10));
+        cache.insert("a", 1, start);
+        cache.insert("b", 2, start);
+        assert_eq!(cache.get(&"a", start), Some(1));
+
+        // "b" is the least recently used
+        cache.insert("c", 3, start);
+        assert_eq!(cache.get(&"b", start), None);
+        assert_eq!(cache.get(&"a", start), Some(1));
+        assert_eq!(cache.get(&"c", start + Duration::from_secs(9)), Some(3));
+        assert_eq!(cache.get(&"c", start + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_route_cache() {
+        let config = RoutingCacheConfig { enabled: true, ..Default::default() };
+        let mut cache = RouteCache::new(&config);
+        let matched = RouteMatch {
+            rules: vec![(0, 2)],
+            semantic: vec![("qwen-coder".to_string(), 1.5)],
+        };
+
+        cache.store_route_match("code", "Write a  Rust\nfunction", None, matched.clone());
+        assert_eq!(cache.route_match("code", "write a rust function ", None), Some(matched));
+        assert_eq!(cache.route_match("review", "write a rust function", None), None);
+        let context = serde_json::json!({ "language": "rust" });
+        assert_eq!(cache.route_match("code", "write a rust function", Some(&context)), None);
+
+        cache.store_session_agent("s1", "code", "codex-helper");
+        cache.reconfigure(&config);
+        assert_eq!(cache.route_match("code", "write a rust function", None), None);
+        assert_eq!(cache.session_agent("s1", "code").as_deref(), Some("codex-helper"));
+        assert_eq!(cache.session_agent("s1", "review"), None);
+
+        let mut disabled = RouteCache::new(&RoutingCacheConfig::default());
+        disabled.store_route_match("code", "prompt", None, RouteMatch::default());
+        assert_eq!(disabled.route_match("code", "prompt", None), None);
+    }
+}
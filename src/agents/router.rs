@@ -1,9 +1,46 @@
-use crate::config::{AgentConfig, RoutingConfig, RoutingRule, RoutingTier};
-use anyhow::Result;
+use crate::agents::matcher::{KeywordMatch, Prompt, RuleMatcher};
+use crate::agents::strategy::{AgentLoads, LoadBalancer};
+use crate::config::{
+    AgentConfig, KeywordMatchMode, LoadBalancingStrategy, RoutingConfig, RoutingMode, RoutingRule, RoutingTier,
+};
+use crate::agents::library::PROMPT_AGENT_TYPE;
+use crate::agents::semantic::Bm25Index;
+use serde::Serialize;
+use serde_json::Value;
 use std::cmp::Ordering;
+use std::sync::{Arc, Mutex};
 
 pub struct AgentRouter {
     routing_config: RoutingConfig,
+    /// Compiled keywords of each rule, same order as `routing_config.rules`
+    matchers: Vec<RuleMatcher>,
+    balancer: LoadBalancer,
+    /// BM25 index and the agent IDs it was built from
+    semantic_index: Mutex<Option<(Vec<String>, Arc<Bm25Index>)>>,
+}
+
+/// Which routing stage put an agent at its place in the ranking
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placement {
+    /// Preferred agent of the n-th matching rule in try order
+    Rule(usize),
+    /// BM25 match with its score
+    Semantic(f64),
+    /// Agent priority fallback
+    Priority,
+    /// `scored` mode
+    Score,
+}
+
+/// Prompt-dependent half of a routing decision: which rules matched and how
+/// well agent descriptions fit. Loads and the load balancer are applied on
+/// top of it on every ranking, so it is what the route cache keeps.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteMatch {
+    /// (config index, keyword hits) of every enabled matching rule
+    pub rules: Vec<(usize, usize)>,
+    /// BM25 (agent ID, score) over every agent, best first (empty when disabled)
+    pub semantic: Vec<(String, f64)>,
 }
 
 /// Routing rule with tier and priority for sorting
@@ -14,6 +51,125 @@ struct ScoredRule<'a> {
     priority: u16,
 }
 
+/// How a rule's conditions fared against a task
+#[derive(Debug, Clone, Default)]
+struct RuleMatch {
+    task_type: bool,
+    keywords: KeywordMatch,
+    /// First context condition that did not hold
+    failed_context: Option<String>,
+}
+
+impl RuleMatch {
+    fn matched(&self) -> bool {
+        self.task_type && self.keywords.matched() && self.failed_context.is_none()
+    }
+}
+
+/// Dry-run account of how a task would be routed. Nothing is executed and
+/// no quota is consumed while building it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteExplanation {
+    pub task_type: String,
+    pub mode: RoutingMode,
+    pub strategy: LoadBalancingStrategy,
+    /// Every configured rule, in config order
+    pub rules: Vec<RuleExplanation>,
+    /// Candidates in the order they would be tried
+    pub ranked_agents: Vec<String>,
+    pub selected_agent: Option<String>,
+    /// `RuleExplanation::rule` of the rule that picked `selected_agent`
+    /// (`None` = priority fallback)
+    pub selected_by_rule: Option<usize>,
+    /// Score of every agent, best first (`scored` mode only)
+    pub scores: Vec<AgentScore>,
+    /// BM25 matches of the semantic stage, best first (when enabled)
+    pub semantic_matches: Vec<SemanticMatch>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticMatch {
+    pub agent_id: String,
+    pub score: f64,
+}
+
+/// How an agent's score was made up in `scored` mode
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentScore {
+    pub agent_id: String,
+    pub score: f64,
+    /// Most keywords matched by one rule preferring the agent
+    pub keyword_hits: usize,
+    /// Agent capabilities matching the task type (0 or 1)
+    pub capability_overlap: usize,
+    /// Best matching rule preferring the agent (1-based config position)
+    pub rule: Option<usize>,
+    /// That rule's tier and priority scaled to 0-1
+    pub rule_rank: f64,
+    pub agent_priority: u8,
+    /// BM25 score relative to the best match (0-1)
+    pub semantic: f64,
+    pub active_tasks: usize,
+    pub has_quota: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleExplanation {
+    /// 1-based position in the config
+    pub rule: usize,
+    pub task_type: String,
+    pub keywords: Vec<String>,
+    pub match_mode: KeywordMatchMode,
+    pub tier: RoutingTier,
+    pub priority: u16,
+    pub enabled: bool,
+    pub task_type_matched: bool,
+    /// True when the rule has no keywords or one was found in the prompt
+    pub keywords_matched: bool,
+    pub matched_keyword: Option<String>,
+    /// True when `any_of` is empty or one of it was found
+    pub any_of_matched: bool,
+    pub matched_any_of: Option<String>,
+    /// Entries of `all_of` not found in the prompt
+    pub missing_all_of: Vec<String>,
+    /// Entry of `exclude_keywords` that ruled the rule out
+    pub excluded_by: Option<String>,
+    /// Context conditions of the rule, as text
+    pub context: Vec<String>,
+    /// First context condition the task context did not satisfy
+    pub failed_context: Option<String>,
+    /// Enabled and every condition matched
+    pub matched: bool,
+    /// Position among the matching rules in the order they are tried
+    pub order: Option<usize>,
+    pub preferred_agents: Vec<PreferredAgentExplanation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreferredAgentExplanation {
+    pub agent_id: String,
+    /// Registered, so routing can use it
+    pub available: bool,
+    pub has_quota: bool,
+    pub active_tasks: usize,
+    /// Filled in by the caller, which owns the rate limiter
+    pub remaining_per_day: Option<u32>,
+    pub remaining_per_minute: Option<u32>,
+}
+
+/// A configured rule that names an agent among its preferred agents
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentRule {
+    /// 1-based position in the config
+    pub rule: usize,
+    /// 1-based place of the agent in `preferred_agents`
+    pub position: usize,
+    pub tier: RoutingTier,
+    #[serde(flatten)]
+    pub config: RoutingRule,
+}
+
 impl<'a> ScoredRule<'a> {
     fn new(rule: &'a RoutingRule, tier: RoutingTier) -> Self {
         Self {
@@ -52,60 +208,148 @@ impl<'a> Ord for ScoredRule<'a> {
 }
 
 impl AgentRouter {
-    pub fn new(routing_config: RoutingConfig) -> Self {
-        Self { routing_config }
+    pub fn new(routing_config: RoutingConfig, strategy: LoadBalancingStrategy) -> Self {
+        let matchers = routing_config.rules.iter().map(RuleMatcher::new).collect();
+
+        Self {
+            routing_config,
+            matchers,
+            balancer: LoadBalancer::new(strategy),
+            semantic_index: Mutex::new(None),
+        }
     }
 
-    /// Select the best agent using tiered priority system
-    pub fn select_agent<'a>(
+    /// Match a task's prompt and context against the rules and the BM25
+    /// index, without looking at loads
+    pub fn match_task(
         &self,
         task_type: &str,
         prompt: &str,
-        available_agents: &'a [&'a AgentConfig],
-    ) -> Result<&'a AgentConfig> {
-        tracing::debug!("🔍 Router: Selecting agent for task_type='{}'", task_type);
+        context: Option<&Value>,
+        available_agents: &[&AgentConfig],
+    ) -> RouteMatch {
         tracing::debug!("📝 Prompt: {}", prompt.chars().take(100).collect::<String>());
 
-        // Find all matching rules
-        let matching_rules: Vec<ScoredRule> = self.routing_config
+        let prompt_text = Prompt::new(prompt);
+        let rules = self.routing_config
             .rules
             .iter()
-            .filter(|rule| rule.enabled && self.rule_matches(rule, task_type, prompt))
-            .map(|rule| ScoredRule::new(rule, self.routing_config.tier.clone()))
+            .zip(&self.matchers)
+            .enumerate()
+            .filter_map(|(index, (rule, matcher))| {
+                let outcome = Self::match_rule(rule, matcher, task_type, &prompt_text, context);
+                (rule.enabled && outcome.matched()).then_some((index, outcome.keywords.hits))
+            })
             .collect();
 
-        tracing::debug!("✅ Found {} matching rules", matching_rules.len());
+        let semantic = if self.routing_config.semantic.enabled {
+            self.semantic_index(available_agents).search(prompt)
+        } else {
+            Vec::new()
+        };
+
+        RouteMatch { rules, semantic }
+    }
+
+    /// Rank every candidate for a matched task, best first, for failover.
+    ///
+    /// Matching rules are walked by tier then priority; each rule contributes
+    /// its preferred agents in strategy order, followed by those that are
+    /// disabled or out of quota. Remaining agents capable of the task type
+    /// follow as the priority fallback. Library personas without a runner are
+    /// never ranked.
+    pub fn rank_matched<'a>(
+        &self,
+        task_type: &str,
+        matched: &RouteMatch,
+        available_agents: &'a [&'a AgentConfig],
+        loads: &AgentLoads,
+    ) -> Vec<&'a AgentConfig> {
+        self.rank_placed(task_type, matched, available_agents, loads, true)
+            .into_iter()
+            .map(|(agent, _)| agent)
+            .collect()
+    }
+
+    /// Ranking with the stage that placed each agent, optionally without
+    /// moving the round-robin cursor
+    fn rank_placed<'a>(
+        &self,
+        task_type: &str,
+        matched: &RouteMatch,
+        available_agents: &'a [&'a AgentConfig],
+        loads: &AgentLoads,
+        advance: bool,
+    ) -> Vec<(&'a AgentConfig, Placement)> {
+        tracing::debug!("🔍 Router: Ranking agents for task_type='{}'", task_type);
+
+        if self.routing_config.mode == RoutingMode::Scored {
+            // Best score first, disabled agents and those without quota last
+            let (mut ranked, exhausted): (Vec<_>, Vec<_>) = self
+                .score_agents(task_type, matched, available_agents, loads)
+                .into_iter()
+                .filter(|(agent, _)| Self::is_runnable(agent, available_agents))
+                .map(|(agent, _)| (agent, Placement::Score))
+                .partition(|(agent, _)| Self::has_quota(agent, loads));
+            ranked.extend(exhausted);
+            return ranked;
+        }
+
+        let mut ranked: Vec<(&'a AgentConfig, Placement)> = Vec::new();
+        let push_unique = |ranked: &mut Vec<(&'a AgentConfig, Placement)>, agent: &'a AgentConfig, placement: Placement| {
+            if Self::is_runnable(agent, available_agents) && !ranked.iter().any(|(a, _)| a.id == agent.id) {
+                ranked.push((agent, placement));
+            }
+        };
+
+        let sorted_rules = self.matching_rules(matched);
+        tracing::debug!("✅ Found {} matching rules", sorted_rules.len());
 
-        if matching_rules.is_empty() {
+        if sorted_rules.is_empty() {
             tracing::debug!("⚠️  No matching rules, falling back to agent priority");
-            return Self::fallback_by_priority(available_agents);
         }
 
-        // Sort by tier (Admin > User > Default) then priority (high > low)
-        let mut sorted_rules = matching_rules;
-        sorted_rules.sort_by(|a, b| b.cmp(a)); // Reverse for highest first
+        // The BM25 stage runs once, after the rules of its tier
+        let semantic = &self.routing_config.semantic;
+        let stage_tier = semantic.stage.after_tier();
+        let mut semantic_pending = semantic.enabled;
+        let push_semantic = |ranked: &mut Vec<(&'a AgentConfig, Placement)>| {
+            let matches = self.semantic_matches(matched);
+            tracing::debug!("🔎 BM25 matches: {:?}", matches);
+
+            let (with_quota, exhausted): (Vec<_>, Vec<_>) = matches
+                .iter()
+                .filter_map(|(agent_id, score)| {
+                    available_agents.iter().find(|a| &a.id == agent_id).map(|agent| (*agent, *score))
+                })
+                .partition(|(agent, _)| Self::has_quota(agent, loads));
+            for (agent, score) in with_quota.into_iter().chain(exhausted) {
+                push_unique(ranked, agent, Placement::Semantic(score));
+            }
+        };
 
         // Try each rule's preferred agents in order
-        for scored_rule in sorted_rules {
+        for (position, scored_rule) in sorted_rules.iter().enumerate() {
+            if semantic_pending && stage_tier.as_ref().is_some_and(|tier| scored_rule.tier < *tier) {
+                push_semantic(&mut ranked);
+                semantic_pending = false;
+            }
+
             tracing::debug!(
-                "🎯 Trying rule: tier={:?}, priority={}, task_type='{}'",
+                "🎯 Trying rule: tier={:?}, priority={}, task_type='{}', strategy={:?}",
                 scored_rule.tier,
                 scored_rule.priority,
-                scored_rule.rule.task_type
+                scored_rule.rule.task_type,
+                self.balancer.strategy()
             );
 
+            let mut candidates: Vec<&'a AgentConfig> = Vec::new();
             for preferred_agent_id in &scored_rule.rule.preferred_agents {
                 if let Some(agent) = available_agents
                     .iter()
                     .find(|a| &a.id == preferred_agent_id)
                 {
-                    tracing::info!(
-                        "✅ Selected agent '{}' via rule (tier={:?}, priority={})",
-                        agent.id,
-                        scored_rule.tier,
-                        scored_rule.priority
-                    );
-                    return Ok(agent);
+                    candidates.push(agent);
                 } else {
                     tracing::debug!(
                         "⏭️  Preferred agent '{}' not available, trying next",
@@ -113,70 +357,411 @@ impl AgentRouter {
                     );
                 }
             }
+
+            // Disabled agents never take the strategy's turn
+            let enabled: Vec<&'a AgentConfig> = candidates.iter().copied().filter(|a| a.enabled).collect();
+            let balanced = if advance {
+                self.balancer.rank(&enabled, loads)
+            } else {
+                self.balancer.preview(&enabled, loads)
+            };
+            for agent in balanced {
+                push_unique(&mut ranked, agent, Placement::Rule(position));
+            }
+            for agent in candidates {
+                push_unique(&mut ranked, agent, Placement::Rule(position));
+            }
+        }
+
+        if semantic_pending {
+            push_semantic(&mut ranked);
+        }
+
+        // Priority fallback for capable agents no rule claimed
+        let mut fallback = self.filter_capable_agents(task_type, available_agents);
+        fallback.sort_by_key(|a| std::cmp::Reverse((Self::has_quota(a, loads), a.priority)));
+        for agent in fallback {
+            push_unique(&mut ranked, agent, Placement::Priority);
+        }
+
+        ranked
+    }
+
+    /// BM25 matches the semantic stage would use: at least `min_score`, `top_k` at most
+    fn semantic_matches(&self, matched: &RouteMatch) -> Vec<(String, f64)> {
+        let semantic = &self.routing_config.semantic;
+        matched.semantic
+            .iter()
+            .filter(|(_, score)| *score >= semantic.min_score)
+            .take(semantic.top_k)
+            .cloned()
+            .collect()
+    }
+
+    /// Index of `agents`, reused until the set of agents changes
+    fn semantic_index(&self, agents: &[&AgentConfig]) -> Arc<Bm25Index> {
+        let mut ids: Vec<String> = agents.iter().map(|agent| agent.id.clone()).collect();
+        ids.sort();
+
+        let mut cached = self.semantic_index.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((indexed_ids, index)) = cached.as_ref() {
+            if *indexed_ids == ids {
+                return index.clone();
+            }
+        }
+
+        tracing::debug!("📇 Building BM25 index over {} agents", agents.len());
+        let index = Arc::new(Bm25Index::build(agents, &self.routing_config.semantic));
+        *cached = Some((ids, index.clone()));
+        index
+    }
+
+    /// Explain the routing decision `rank_agents` and the quota claim in
+    /// `delegate_task` would make, rule by rule
+    pub fn explain<'a>(
+        &self,
+        task_type: &str,
+        prompt: &str,
+        context: Option<&Value>,
+        available_agents: &'a [&'a AgentConfig],
+        loads: &AgentLoads,
+    ) -> RouteExplanation {
+        let matched = self.match_task(task_type, prompt, context, available_agents);
+        let matching = self.matching_rules(&matched);
+        let index_of = |rule: &RoutingRule| {
+            self.routing_config.rules.iter().position(|r| std::ptr::eq(r, rule)).unwrap_or(0) + 1
+        };
+
+        let prompt_text = Prompt::new(prompt);
+        let rules = self.routing_config
+            .rules
+            .iter()
+            .zip(&self.matchers)
+            .enumerate()
+            .map(|(index, (rule, matcher))| {
+                let outcome = Self::match_rule(rule, matcher, task_type, &prompt_text, context);
+                RuleExplanation {
+                    rule: index + 1,
+                    task_type: rule.task_type.clone(),
+                    keywords: rule.keywords.clone(),
+                    match_mode: rule.match_mode,
+                    tier: rule.tier.clone().unwrap_or_else(|| self.routing_config.tier.clone()),
+                    priority: rule.priority,
+                    enabled: rule.enabled,
+                    task_type_matched: outcome.task_type,
+                    matched: rule.enabled && outcome.matched(),
+                    keywords_matched: outcome.keywords.keywords,
+                    matched_keyword: outcome.keywords.keyword,
+                    any_of_matched: outcome.keywords.any_of_matched,
+                    matched_any_of: outcome.keywords.any_of,
+                    missing_all_of: outcome.keywords.missing_all_of,
+                    excluded_by: outcome.keywords.excluded_by,
+                    context: rule.context.iter().map(ToString::to_string).collect(),
+                    failed_context: outcome.failed_context,
+                    order: matching
+                        .iter()
+                        .position(|scored| std::ptr::eq(scored.rule, rule))
+                        .map(|position| position + 1),
+                    preferred_agents: rule.preferred_agents
+                        .iter()
+                        .map(|agent_id| {
+                            let load = loads.get(agent_id).cloned().unwrap_or_default();
+                            PreferredAgentExplanation {
+                                agent_id: agent_id.clone(),
+                                available: available_agents
+                                    .iter()
+                                    .any(|a| &a.id == agent_id && a.enabled && Self::is_runnable(a, available_agents)),
+                                has_quota: load.has_quota,
+                                active_tasks: load.active_tasks,
+                                remaining_per_day: None,
+                                remaining_per_minute: None,
+                            }
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let placed = self.rank_placed(task_type, &matched, available_agents, loads, false);
+        let ranked: Vec<&AgentConfig> = placed.iter().map(|(agent, _)| *agent).collect();
+        let selected = placed.iter().find(|(agent, _)| Self::has_quota(agent, loads)).copied();
+        let semantic_matches: Vec<SemanticMatch> = self
+            .semantic_matches(&matched)
+            .into_iter()
+            .map(|(agent_id, score)| SemanticMatch { agent_id, score })
+            .collect();
+
+        if self.routing_config.mode == RoutingMode::Scored {
+            let scores: Vec<AgentScore> = self
+                .score_agents(task_type, &matched, available_agents, loads)
+                .into_iter()
+                .map(|(_, score)| score)
+                .collect();
+            let selected_score = selected
+                .and_then(|(agent, _)| scores.iter().find(|score| score.agent_id == agent.id));
+
+            let reason = match selected_score {
+                Some(score) => format!(
+                    "Scored routing picked '{}' with {:.2} ({} keyword hit(s), {} shared capability(ies), rule rank {:.2}, agent priority {}, semantic {:.2}, {} active task(s))",
+                    score.agent_id, score.score, score.keyword_hits, score.capability_overlap,
+                    score.rule_rank, score.agent_priority, score.semantic, score.active_tasks
+                ),
+                None if ranked.is_empty() => format!("No registered agent can run task_type '{}'", task_type),
+                None => "Every candidate is disabled or out of quota; the task would fail".to_string(),
+            };
+
+            return RouteExplanation {
+                task_type: task_type.to_string(),
+                mode: RoutingMode::Scored,
+                strategy: self.balancer.strategy(),
+                rules,
+                ranked_agents: ranked.iter().map(|agent| agent.id.clone()).collect(),
+                selected_agent: selected.map(|(agent, _)| agent.id.clone()),
+                selected_by_rule: selected_score.and_then(|score| score.rule),
+                reason,
+                scores,
+                semantic_matches,
+            };
+        }
+
+        let selected_rule = match selected {
+            Some((_, Placement::Rule(position))) => Some((position, &matching[position])),
+            _ => None,
+        };
+
+        let mut reason = match (selected, &selected_rule) {
+            (Some((agent, Placement::Semantic(score))), _) => format!(
+                "BM25 match on agent descriptions picked '{}' (score {:.2}) at the {:?} stage",
+                agent.id,
+                score,
+                self.routing_config.semantic.stage
+            ),
+            (Some((agent, _)), Some((position, scored))) => {
+                let mut reason = format!(
+                    "Rule #{} (tier {:?}, priority {}) picked '{}' with the {:?} strategy",
+                    index_of(scored.rule), scored.tier, scored.priority, agent.id, self.balancer.strategy()
+                );
+                if *position > 0 {
+                    reason.push_str(&format!(
+                        " after {} higher-ranked matching rule(s) had no available agent with quota",
+                        position
+                    ));
+                }
+                reason
+            }
+            (Some((agent, _)), None) if matching.is_empty() => format!(
+                "No enabled rule matched task_type '{}' and the prompt; falling back to the highest-priority agent with quota ('{}')",
+                task_type, agent.id
+            ),
+            (Some((agent, _)), None) => format!(
+                "No matching rule has an available agent with quota; falling back to the highest-priority agent with quota ('{}')",
+                agent.id
+            ),
+            (None, _) if ranked.is_empty() => format!("No registered agent can run task_type '{}'", task_type),
+            (None, _) => "Every candidate is disabled or out of quota; the task would fail".to_string(),
+        };
+
+        if selected_rule.is_some() && self.balancer.strategy() == LoadBalancingStrategy::WeightedRandom {
+            reason.push_str(" (weighted-random: the pick varies per request)");
         }
 
-        // No rule found an available agent, fallback to priority
-        tracing::debug!("⚠️  No rule matched available agents, falling back");
-        Self::fallback_by_priority(available_agents)
+        RouteExplanation {
+            task_type: task_type.to_string(),
+            mode: RoutingMode::FirstMatch,
+            strategy: self.balancer.strategy(),
+            rules,
+            ranked_agents: ranked.iter().map(|agent| agent.id.clone()).collect(),
+            selected_agent: selected.map(|(agent, _)| agent.id.clone()),
+            selected_by_rule: selected_rule.map(|(_, scored)| index_of(scored.rule)),
+            scores: Vec::new(),
+            semantic_matches,
+            reason,
+        }
     }
 
-    /// Check if a rule matches the task
-    fn rule_matches(&self, rule: &RoutingRule, task_type: &str, prompt: &str) -> bool {
-        // Check task_type
-        if rule.task_type != task_type {
-            return false;
+    /// Score every agent for the task (see `ScoringWeights`), best first.
+    /// Ties keep the order of `available_agents`.
+    fn score_agents<'a>(
+        &self,
+        task_type: &str,
+        matched: &RouteMatch,
+        available_agents: &'a [&'a AgentConfig],
+        loads: &AgentLoads,
+    ) -> Vec<(&'a AgentConfig, AgentScore)> {
+        let weights = &self.routing_config.weights;
+
+        // (config position, rule, keyword hits) of every matching rule
+        let matching: Vec<(usize, &RoutingRule, usize)> = matched.rules
+            .iter()
+            .filter_map(|&(index, hits)| self.routing_config.rules.get(index).map(|rule| (index, rule, hits)))
+            .collect();
+
+        // Relative BM25 score, so the weight means the same for every prompt
+        let semantic = &matched.semantic;
+        let best_semantic = semantic.first().map(|(_, score)| *score).unwrap_or(1.0);
+
+        // Only the task type counts: the matching rules are already scored by
+        // keyword hits and rule rank
+        let inferred = [task_type.to_string()];
+
+        let mut scored: Vec<(&'a AgentConfig, AgentScore)> = available_agents
+            .iter()
+            .map(|&agent| {
+                let preferring: Vec<&(usize, &RoutingRule, usize)> = matching
+                    .iter()
+                    .filter(|(_, rule, _)| rule.preferred_agents.contains(&agent.id))
+                    .collect();
+                let keyword_hits = preferring.iter().map(|(_, _, hits)| *hits).max().unwrap_or(0);
+                let best_rule = preferring
+                    .iter()
+                    .map(|(index, rule, _)| (*index, self.rule_rank(rule)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                let rule_rank = best_rule.map(|(_, rank)| rank).unwrap_or(0.0);
+
+                let capability_overlap = agent.capabilities
+                    .iter()
+                    .filter(|capability| inferred.contains(capability))
+                    .count();
+                let load = loads.get(&agent.id).cloned().unwrap_or_default();
+                let semantic_score = semantic
+                    .iter()
+                    .find(|(agent_id, _)| agent_id == &agent.id)
+                    .map(|(_, score)| score / best_semantic)
+                    .unwrap_or(0.0);
+
+                let score = weights.keyword_hits * keyword_hits as f64
+                    + weights.capability_overlap * capability_overlap as f64
+                    + weights.rule_priority * rule_rank
+                    + weights.agent_priority * (agent.priority as f64 / u8::MAX as f64)
+                    + weights.semantic * semantic_score
+                    - weights.load * load.active_tasks as f64;
+
+                (agent, AgentScore {
+                    agent_id: agent.id.clone(),
+                    score,
+                    keyword_hits,
+                    capability_overlap,
+                    rule: best_rule.map(|(index, _)| index + 1),
+                    rule_rank,
+                    agent_priority: agent.priority,
+                    semantic: semantic_score,
+                    active_tasks: load.active_tasks,
+                    has_quota: load.has_quota,
+                })
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+        scored
+    }
+
+    /// Tier and priority of a rule on one 0-1 scale; a higher tier always ranks higher
+    fn rule_rank(&self, rule: &RoutingRule) -> f64 {
+        let tier = match rule.tier.clone().unwrap_or_else(|| self.routing_config.tier.clone()) {
+            RoutingTier::Default => 0,
+            RoutingTier::User => 1,
+            RoutingTier::Admin => 2,
+        };
+        (tier * 1000 + rule.priority.min(999)) as f64 / 2999.0
+    }
+
+    /// Every capability of the rules' available preferred agents
+    fn rule_capabilities<'r>(
+        rules: impl Iterator<Item = &'r RoutingRule>,
+        agents: &[&AgentConfig],
+    ) -> Vec<String> {
+        let mut capabilities: Vec<String> = Vec::new();
+        for rule in rules {
+            for agent in agents.iter().filter(|a| rule.preferred_agents.contains(&a.id)) {
+                for capability in &agent.capabilities {
+                    if !capabilities.contains(capability) {
+                        capabilities.push(capability.clone());
+                    }
+                }
+            }
         }
+        capabilities
+    }
+
+    /// Enabled rules matching the task, sorted by tier (Admin > User > Default)
+    /// then priority (high > low)
+    fn matching_rules(&self, matched: &RouteMatch) -> Vec<ScoredRule<'_>> {
+        let mut matching_rules: Vec<ScoredRule> = matched.rules
+            .iter()
+            .filter_map(|&(index, _)| self.routing_config.rules.get(index))
+            .map(|rule| {
+                let tier = rule.tier.clone().unwrap_or_else(|| self.routing_config.tier.clone());
+                ScoredRule::new(rule, tier)
+            })
+            .collect();
+
+        matching_rules.sort_by(|a, b| b.cmp(a)); // Reverse for highest first
+        matching_rules
+    }
 
-        // Check keywords (if any)
-        if rule.keywords.is_empty() {
+    /// Library personas run on their runner, which must be registered
+    fn is_runnable(agent: &AgentConfig, available_agents: &[&AgentConfig]) -> bool {
+        if agent.agent_type != PROMPT_AGENT_TYPE {
             return true;
         }
+        agent.persona
+            .as_ref()
+            .and_then(|persona| persona.runner.as_deref())
+            .is_some_and(|runner| available_agents.iter().any(|a| a.id == runner))
+    }
+
+    /// Whether the agent can take a task now; disabled agents never can
+    fn has_quota(agent: &AgentConfig, loads: &AgentLoads) -> bool {
+        agent.enabled && loads.get(&agent.id).map(|load| load.has_quota).unwrap_or(true)
+    }
 
-        let prompt_lower = prompt.to_lowercase();
-        rule.keywords.iter().any(|keyword| {
-            prompt_lower.contains(&keyword.to_lowercase())
-        })
+    /// Evaluate each condition of a rule separately, for explanations
+    fn match_rule(
+        rule: &RoutingRule,
+        matcher: &RuleMatcher,
+        task_type: &str,
+        prompt: &Prompt,
+        context: Option<&Value>,
+    ) -> RuleMatch {
+        RuleMatch {
+            task_type: rule.task_type == task_type,
+            keywords: matcher.evaluate(prompt),
+            failed_context: matcher.failed_context(context),
+        }
     }
 
-    /// Fallback: select highest priority available agent
-    fn fallback_by_priority<'a>(
-        available_agents: &'a [&'a AgentConfig]
-    ) -> Result<&'a AgentConfig> {
-        available_agents
+    /// Rules that prefer `agent_id`, in config order, disabled ones included
+    pub fn rules_for_agent(&self, agent_id: &str) -> Vec<AgentRule> {
+        self.routing_config
+            .rules
             .iter()
-            .max_by_key(|a| a.priority)
-            .copied()
-            .ok_or_else(|| anyhow::anyhow!("No available agents"))
-            .map(|agent| {
-                tracing::info!(
-                    "✅ Selected agent '{}' by priority fallback (priority={})",
-                    agent.id,
-                    agent.priority
-                );
-                agent
+            .enumerate()
+            .filter_map(|(index, rule)| {
+                let position = rule.preferred_agents.iter().position(|id| id == agent_id)?;
+                Some(AgentRule {
+                    rule: index + 1,
+                    position: position + 1,
+                    tier: rule.tier.clone().unwrap_or_else(|| self.routing_config.tier.clone()),
+                    config: rule.clone(),
+                })
             })
+            .collect()
     }
 
     /// Get agents that match task requirements
     pub fn filter_capable_agents<'a>(
         &self,
         task_type: &str,
-        all_agents: &'a [&'a AgentConfig],
+        all_agents: &[&'a AgentConfig],
     ) -> Vec<&'a AgentConfig> {
-        // Extract capabilities from matching rules
-        let required_capabilities: Vec<String> = self.routing_config
-            .rules
-            .iter()
-            .filter(|rule| rule.enabled && rule.task_type == task_type)
-            .flat_map(|rule| {
-                rule.preferred_agents.iter().filter_map(|agent_id| {
-                    all_agents
-                        .iter()
-                        .find(|a| &a.id == agent_id)
-                        .and_then(|a| a.capabilities.first().cloned())
-                })
-            })
-            .collect();
+        // Extract capabilities from matching rules (all of each agent's, not just the first)
+        let required_capabilities = Self::rule_capabilities(
+            self.routing_config
+                .rules
+                .iter()
+                .filter(|rule| rule.enabled && rule.task_type == task_type),
+            all_agents,
+        );
 
         if required_capabilities.is_empty() {
             return all_agents.to_vec();
@@ -197,7 +782,41 @@ impl AgentRouter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::RateLimit;
+    use crate::agents::strategy::AgentLoad;
+    use crate::config::{ContextCondition, RateLimit, SemanticRoutingConfig, SemanticStage};
+
+    impl AgentRouter {
+        /// Match and rank in one go
+        fn rank_agents<'a>(
+            &self,
+            task_type: &str,
+            prompt: &str,
+            context: Option<&Value>,
+            available_agents: &'a [&'a AgentConfig],
+            loads: &AgentLoads,
+        ) -> Vec<&'a AgentConfig> {
+            let matched = self.match_task(task_type, prompt, context, available_agents);
+            self.rank_matched(task_type, &matched, available_agents, loads)
+        }
+
+        /// The agent `delegate_task` would claim: the first ranked one with quota
+        fn select_agent<'a>(
+            &self,
+            task_type: &str,
+            prompt: &str,
+            context: Option<&Value>,
+            available_agents: &'a [&'a AgentConfig],
+            loads: &AgentLoads,
+        ) -> anyhow::Result<&'a AgentConfig> {
+            let ranked = self.rank_agents(task_type, prompt, context, available_agents, loads);
+            ranked
+                .iter()
+                .find(|agent| Self::has_quota(agent, loads))
+                .or_else(|| ranked.first())
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("No available agents"))
+        }
+    }
 
     fn create_test_agent(id: &str, capabilities: Vec<&str>, priority: u8) -> AgentConfig {
         AgentConfig {
@@ -211,6 +830,7 @@ mod tests {
             capabilities: capabilities.iter().map(|s| s.to_string()).collect(),
             priority,
             enabled: true,
+            ..Default::default()
         }
     }
 
@@ -225,6 +845,8 @@ mod tests {
                     preferred_agents: vec!["high-priority".to_string()],
                     priority: 900,
                     enabled: true,
+                    tier: None,
+                    ..Default::default()
                 },
                 RoutingRule {
                     task_type: "test".to_string(),
@@ -232,13 +854,16 @@ mod tests {
                     preferred_agents: vec!["low-priority".to_string()],
                     priority: 100,
                     enabled: true,
+                    tier: None,
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
-        let router = AgentRouter::new(routing_config);
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
 
-        let agents = vec![
+        let agents = [
             create_test_agent("high-priority", vec!["test"], 1),
             create_test_agent("low-priority", vec!["test"], 2),
         ];
@@ -246,13 +871,49 @@ mod tests {
         let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
 
         let selected = router
-            .select_agent("test", "any prompt", &agent_refs)
+            .select_agent("test", "any prompt", None, &agent_refs, &AgentLoads::new())
             .unwrap();
 
         // Should select high-priority rule first
         assert_eq!(selected.id, "high-priority");
     }
 
+    #[test]
+    fn test_rule_tier_beats_priority() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "test".to_string(),
+                    preferred_agents: vec!["user-pick".to_string()],
+                    priority: 999,
+                    tier: Some(RoutingTier::User),
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "test".to_string(),
+                    preferred_agents: vec!["admin-pick".to_string()],
+                    priority: 1,
+                    tier: Some(RoutingTier::Admin),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+        let agents = [
+            create_test_agent("user-pick", vec!["test"], 1),
+            create_test_agent("admin-pick", vec!["test"], 1),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let selected = router
+            .select_agent("test", "any prompt", None, &agent_refs, &AgentLoads::new())
+            .unwrap();
+        assert_eq!(selected.id, "admin-pick");
+    }
+
     #[test]
     fn test_keyword_matching() {
         let routing_config = RoutingConfig {
@@ -264,13 +925,16 @@ mod tests {
                     preferred_agents: vec!["rust-agent".to_string()],
                     priority: 500,
                     enabled: true,
+                    tier: None,
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
-        let router = AgentRouter::new(routing_config);
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
 
-        let agents = vec![
+        let agents = [
             create_test_agent("rust-agent", vec!["code"], 1),
         ];
 
@@ -278,15 +942,478 @@ mod tests {
 
         // Should match with "rust" keyword
         let selected = router
-            .select_agent("code", "write rust code", &agent_refs)
+            .select_agent("code", "write rust code", None, &agent_refs, &AgentLoads::new())
             .unwrap();
         assert_eq!(selected.id, "rust-agent");
 
         // Should NOT match without keyword
         let result = router
-            .select_agent("code", "write python code", &agent_refs);
+            .select_agent("code", "write python code", None, &agent_refs, &AgentLoads::new());
         
         // Falls back to priority
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_strategy_skips_agent_without_quota() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec![],
+                    preferred_agents: vec!["qwen".to_string(), "codex".to_string()],
+                    priority: 500,
+                    enabled: true,
+                    tier: None,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::FirstWithQuota);
+
+        let agents = [
+            create_test_agent("qwen", vec!["code"], 1),
+            create_test_agent("codex", vec!["code"], 1),
+            create_test_agent("fallback", vec!["code"], 200),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let exhausted = |ids: &[&str]| -> AgentLoads {
+            ids.iter()
+                .map(|id| (id.to_string(), AgentLoad { active_tasks: 0, has_quota: false }))
+                .collect()
+        };
+
+        let selected = router
+            .select_agent("code", "prompt", None, &agent_refs, &AgentLoads::new())
+            .unwrap();
+        assert_eq!(selected.id, "qwen");
+
+        let selected = router
+            .select_agent("code", "prompt", None, &agent_refs, &exhausted(&["qwen"]))
+            .unwrap();
+        assert_eq!(selected.id, "codex");
+
+        // Whole rule exhausted: priority fallback picks an agent that has quota
+        let selected = router
+            .select_agent("code", "prompt", None, &agent_refs, &exhausted(&["qwen", "codex"]))
+            .unwrap();
+        assert_eq!(selected.id, "fallback");
+    }
+
+    #[test]
+    fn test_rank_agents_for_failover() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec![],
+                    preferred_agents: vec!["qwen".to_string(), "codex".to_string()],
+                    priority: 500,
+                    enabled: true,
+                    tier: None,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::FirstWithQuota);
+
+        let agents = [
+            create_test_agent("low", vec!["code"], 10),
+            create_test_agent("qwen", vec!["code"], 1),
+            create_test_agent("codex", vec!["code"], 1),
+            create_test_agent("high", vec!["code"], 200),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+        let loads = AgentLoads::from([(
+            "qwen".to_string(),
+            AgentLoad { active_tasks: 0, has_quota: false },
+        )]);
+
+        let ranked: Vec<&str> = router
+            .rank_agents("code", "prompt", None, &agent_refs, &loads)
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect();
+
+        // Rule agents first (exhausted ones last within the rule), then priority fallback
+        assert_eq!(ranked, vec!["codex", "qwen", "high", "low"]);
+    }
+
+    #[test]
+    fn test_explain_route() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec!["python".to_string(), "Rust".to_string()],
+                    preferred_agents: vec!["qwen".to_string(), "ghost".to_string()],
+                    priority: 900,
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    preferred_agents: vec!["codex".to_string()],
+                    priority: 100,
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "docs".to_string(),
+                    preferred_agents: vec!["codex".to_string()],
+                    priority: 999,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::RoundRobin);
+        let agents = [
+            create_test_agent("qwen", vec!["code"], 1),
+            create_test_agent("codex", vec!["code"], 1),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+        let loads = AgentLoads::from([(
+            "qwen".to_string(),
+            AgentLoad { active_tasks: 2, has_quota: false },
+        )]);
+
+        let explanation = router.explain("code", "fix this rust bug", None, &agent_refs, &loads);
+
+        let first = &explanation.rules[0];
+        assert!(first.matched && first.task_type_matched && first.keywords_matched);
+        assert_eq!(first.matched_keyword.as_deref(), Some("Rust"));
+        assert_eq!(first.order, Some(1));
+        assert!(!first.preferred_agents[0].has_quota);
+        assert_eq!(first.preferred_agents[0].active_tasks, 2);
+        assert!(!first.preferred_agents[1].available);
+
+        assert_eq!(explanation.rules[1].order, Some(2));
+        assert!(!explanation.rules[2].task_type_matched);
+        assert_eq!(explanation.rules[2].order, None);
+
+        // qwen is out of quota, so the second rule decides
+        assert_eq!(explanation.selected_agent.as_deref(), Some("codex"));
+        assert_eq!(explanation.selected_by_rule, Some(2));
+        assert!(explanation.reason.contains("after 1 higher-ranked"), "{}", explanation.reason);
+
+        // A dry run leaves the round-robin cursor alone
+        router.explain("code", "rust", None, &agent_refs, &AgentLoads::new());
+        let selected = router.select_agent("code", "rust", None, &agent_refs, &AgentLoads::new()).unwrap();
+        assert_eq!(selected.id, "qwen");
+
+        let explanation = router.explain("deploy", "ship it", None, &agent_refs, &AgentLoads::new());
+        assert_eq!(explanation.selected_by_rule, None);
+        assert!(explanation.reason.starts_with("No enabled rule matched"));
+    }
+
+    #[test]
+    fn test_fallback_only_ranks_capable_runnable_agents() {
+        let routing_config = RoutingConfig {
+            rules: vec![RoutingRule {
+                task_type: "code".to_string(),
+                preferred_agents: vec!["qwen".to_string()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+
+        let persona = |id: &str, runner: Option<&str>| AgentConfig {
+            agent_type: PROMPT_AGENT_TYPE.to_string(),
+            command: None,
+            persona: Some(crate::config::AgentPersona {
+                runner: runner.map(str::to_string),
+                ..Default::default()
+            }),
+            ..create_test_agent(id, vec!["code"], 100)
+        };
+        let agents = [
+            create_test_agent("qwen", vec!["code"], 1),
+            create_test_agent("docs-writer", vec!["docs"], 200),
+            persona("pirate", None),
+            persona("reviewer", Some("qwen")),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let ranked: Vec<&str> = router
+            .rank_agents("code", "anything", None, &agent_refs, &AgentLoads::new())
+            .iter()
+            .map(|agent| agent.id.as_str())
+            .collect();
+        assert_eq!(ranked, vec!["qwen", "reviewer"]);
+    }
+
+    #[test]
+    fn test_rules_for_agent() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::User,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    preferred_agents: vec!["qwen".to_string(), "codex".to_string()],
+                    tier: Some(RoutingTier::Admin),
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "docs".to_string(),
+                    preferred_agents: vec!["gemini".to_string()],
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "review".to_string(),
+                    preferred_agents: vec!["codex".to_string()],
+                    enabled: false,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+
+        let rules = router.rules_for_agent("codex");
+        let found: Vec<(usize, usize, RoutingTier)> = rules.iter().map(|r| (r.rule, r.position, r.tier.clone())).collect();
+        assert_eq!(found, vec![(1, 2, RoutingTier::Admin), (3, 1, RoutingTier::User)]);
+        assert!(!rules[1].config.enabled);
+
+        let json = serde_json::to_value(&rules[0]).unwrap();
+        assert_eq!(json["task_type"], "code");
+        assert_eq!(json["tier"], "admin");
+
+        assert!(router.rules_for_agent("nobody").is_empty());
+    }
+
+    #[test]
+    fn test_whole_word_and_excluded_keywords() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec!["rust".to_string(), "test".to_string()],
+                    match_mode: KeywordMatchMode::WholeWord,
+                    exclude_keywords: vec!["wasm".to_string()],
+                    preferred_agents: vec!["rust-agent".to_string()],
+                    priority: 500,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+        let agents = [
+            create_test_agent("rust-agent", vec!["code"], 1),
+            create_test_agent("fallback", vec!["code"], 100),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+        let pick = |prompt: &str| {
+            router.select_agent("code", prompt, None, &agent_refs, &AgentLoads::new()).unwrap().id.clone()
+        };
+
+        assert_eq!(pick("write a Rust test"), "rust-agent");
+        assert_eq!(pick("upgrade to the latest version"), "fallback");
+        assert_eq!(pick("compile rust to wasm"), "fallback");
+
+        let explanation = router.explain("code", "compile rust to wasm", None, &agent_refs, &AgentLoads::new());
+        assert_eq!(explanation.rules[0].excluded_by.as_deref(), Some("wasm"));
+        assert!(!explanation.rules[0].matched);
+    }
+
+    #[test]
+    fn test_scored_routing() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec!["rust".to_string(), "async".to_string()],
+                    preferred_agents: vec!["rustacean".to_string()],
+                    priority: 100,
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec!["rust".to_string()],
+                    preferred_agents: vec!["generalist".to_string()],
+                    priority: 900,
+                    ..Default::default()
+                },
+            ],
+            mode: RoutingMode::Scored,
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+        let agents = [
+            create_test_agent("generalist", vec!["code"], 1),
+            create_test_agent("rustacean", vec!["code", "rust"], 1),
+            create_test_agent("writer", vec!["docs"], 255),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        // First-match would take the priority-900 rule; two keyword hits win here
+        let ranked: Vec<&str> = router
+            .rank_agents("code", "async rust server", None, &agent_refs, &AgentLoads::new())
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect();
+        assert_eq!(ranked, vec!["rustacean", "generalist", "writer"]);
+
+        // Enough load tips it back
+        let busy = AgentLoads::from([(
+            "rustacean".to_string(),
+            AgentLoad { active_tasks: 4, has_quota: true },
+        )]);
+        let selected = router.select_agent("code", "async rust server", None, &agent_refs, &busy).unwrap();
+        assert_eq!(selected.id, "generalist");
+
+        let explanation = router.explain("code", "async rust server", None, &agent_refs, &AgentLoads::new());
+        let best = &explanation.scores[0];
+        assert_eq!(best.agent_id, "rustacean");
+        assert_eq!((best.keyword_hits, best.capability_overlap, best.rule), (2, 1, Some(1)));
+        assert_eq!(explanation.selected_by_rule, Some(1));
+    }
+
+    #[test]
+    fn test_filter_capable_agents_uses_every_capability() {
+        let routing_config = RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    preferred_agents: vec!["polyglot".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::default());
+        let agents = [
+            create_test_agent("polyglot", vec!["python", "rust"], 1),
+            create_test_agent("rust-only", vec!["rust"], 1),
+            create_test_agent("writer", vec!["docs"], 1),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let capable: Vec<&str> = router
+            .filter_capable_agents("code", &agent_refs)
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect();
+        assert_eq!(capable, vec!["polyglot", "rust-only"]);
+    }
+
+    #[test]
+    fn test_semantic_stage() {
+        let mut writer = create_test_agent("writer", vec!["docs"], 1);
+        writer.description = Some("Writes README files and API documentation".to_string());
+        let mut reviewer = create_test_agent("reviewer", vec!["review"], 1);
+        reviewer.description = Some("Reviews pull requests and diffs".to_string());
+        let agents = [
+            create_test_agent("generalist", vec!["code"], 200),
+            writer,
+            reviewer,
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let routing_config = |stage: SemanticStage| RoutingConfig {
+            tier: RoutingTier::Default,
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    keywords: vec!["diff".to_string()],
+                    preferred_agents: vec!["generalist".to_string()],
+                    priority: 500,
+                    ..Default::default()
+                },
+            ],
+            semantic: SemanticRoutingConfig {
+                enabled: true,
+                stage,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pick = |router: &AgentRouter, prompt: &str| {
+            router.select_agent("code", prompt, None, &agent_refs, &AgentLoads::new()).unwrap().id.clone()
+        };
+
+        // No rule matches: BM25 beats the priority fallback
+        let fallback = AgentRouter::new(routing_config(SemanticStage::Fallback), LoadBalancingStrategy::default());
+        assert_eq!(pick(&fallback, "update the README documentation"), "writer");
+        assert_eq!(pick(&fallback, "review this diff"), "generalist");
+        let explanation = fallback.explain("code", "update the README documentation", None, &agent_refs, &AgentLoads::new());
+        assert!(explanation.reason.starts_with("BM25 match"), "{}", explanation.reason);
+        assert_eq!(explanation.semantic_matches[0].agent_id, "writer");
+
+        // Stage after the user tier runs before default-tier rules
+        let early = AgentRouter::new(routing_config(SemanticStage::User), LoadBalancingStrategy::default());
+        assert_eq!(pick(&early, "review this diff"), "reviewer");
+    }
+
+    #[test]
+    fn test_context_conditions() {
+        let agents = [
+            create_test_agent("rust-agent", vec!["code"], 10),
+            create_test_agent("big-repo-agent", vec!["code"], 20),
+            create_test_agent("generalist", vec!["code"], 90),
+        ];
+        let agent_refs: Vec<&AgentConfig> = agents.iter().collect();
+        let condition = |pointer: &str| ContextCondition {
+            pointer: pointer.to_string(),
+            ..Default::default()
+        };
+
+        let routing_config = RoutingConfig {
+            rules: vec![
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    context: vec![
+                        ContextCondition { equals: Some(serde_json::json!("rust")), ..condition("/language") },
+                        ContextCondition { glob: Some("github.com/acme/*".to_string()), ..condition("/repo") },
+                    ],
+                    preferred_agents: vec!["rust-agent".to_string()],
+                    priority: 200,
+                    ..Default::default()
+                },
+                RoutingRule {
+                    task_type: "code".to_string(),
+                    context: vec![ContextCondition { gt: Some(50.0), ..condition("/file_count") }],
+                    preferred_agents: vec!["big-repo-agent".to_string()],
+                    priority: 100,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let router = AgentRouter::new(routing_config, LoadBalancingStrategy::RoundRobin);
+        let pick = |context: serde_json::Value| {
+            router.select_agent("code", "refactor", Some(&context), &agent_refs, &AgentLoads::new()).unwrap().id.clone()
+        };
+
+        let acme_rust = serde_json::json!({ "language": "rust", "repo": "github.com/acme/api", "file_count": 80 });
+        assert_eq!(pick(acme_rust), "rust-agent");
+        let other_repo = serde_json::json!({ "language": "rust", "repo": "gitlab.com/acme/api", "file_count": 80 });
+        assert_eq!(pick(other_repo), "big-repo-agent");
+        assert_eq!(pick(serde_json::json!({ "file_count": 12 })), "generalist");
+
+        // No context: every rule with context conditions is skipped
+        let selected = router.select_agent("code", "refactor", None, &agent_refs, &AgentLoads::new()).unwrap();
+        assert_eq!(selected.id, "generalist");
+
+        let context = serde_json::json!({ "language": "go", "file_count": 80 });
+        let explanation = router.explain("code", "refactor", Some(&context), &agent_refs, &AgentLoads::new());
+        assert_eq!(explanation.rules[0].failed_context.as_deref(), Some("/language == \"rust\""));
+        assert!(explanation.rules[1].matched);
+        assert_eq!(explanation.selected_agent.as_deref(), Some("big-repo-agent"));
+    }
 }
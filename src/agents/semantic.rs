@@ -0,0 +1,187 @@
+//! Local BM25 index over agent descriptions, for prompts no keyword rule catches.
+//!
+//! Each agent is one document made of its id, name, capabilities, category,
+//! description and library persona (use cases and system prompt). Nothing
+//! leaves the process.
+
+use crate::config::{AgentConfig, SemanticRoutingConfig};
+use std::collections::HashMap;
+
+/// Common words that say nothing about which agent fits
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "for", "from", "how",
+    "i", "in", "into", "is", "it", "me", "my", "of", "on", "or", "our", "please", "so", "that",
+    "the", "their", "this", "to", "us", "we", "what", "when", "with", "you", "your",
+];
+
+struct Document {
+    agent_id: String,
+    terms: HashMap<String, usize>,
+    length: usize,
+}
+
+pub struct Bm25Index {
+    documents: Vec<Document>,
+    /// Number of documents containing each term
+    document_frequency: HashMap<String, usize>,
+    average_length: f64,
+    k1: f64,
+    b: f64,
+}
+
+impl Bm25Index {
+    pub fn build(agents: &[&AgentConfig], config: &SemanticRoutingConfig) -> Self {
+        let documents: Vec<Document> = agents
+            .iter()
+            .map(|agent| {
+                let tokens = tokenize(&document_text(agent));
+                let mut terms = HashMap::new();
+                for token in &tokens {
+                    *terms.entry(token.clone()).or_insert(0) += 1;
+                }
+                Document {
+                    agent_id: agent.id.clone(),
+                    terms,
+                    length: tokens.len(),
+                }
+            })
+            .collect();
+
+        let mut document_frequency = HashMap::new();
+        for document in &documents {
+            for term in document.terms.keys() {
+                *document_frequency.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let total_length: usize = documents.iter().map(|d| d.length).sum();
+        let average_length = if documents.is_empty() {
+            0.0
+        } else {
+            total_length as f64 / documents.len() as f64
+        };
+
+        Self {
+            documents,
+            document_frequency,
+            average_length,
+            k1: config.k1,
+            b: config.b,
+        }
+    }
+
+    /// Agents scoring above zero for the prompt, best first
+    pub fn search(&self, prompt: &str) -> Vec<(String, f64)> {
+        let mut query = tokenize(prompt);
+        query.sort();
+        query.dedup();
+
+        let total = self.documents.len() as f64;
+        let mut results: Vec<(String, f64)> = self
+            .documents
+            .iter()
+            .map(|document| {
+                let score: f64 = query
+                    .iter()
+                    .filter_map(|term| {
+                        let frequency = *document.terms.get(term)? as f64;
+                        let containing = self.document_frequency[term] as f64;
+                        let idf = ((total - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                        let length_norm = 1.0 - self.b
+                            + self.b * document.length as f64 / self.average_length.max(1.0);
+                        Some(idf * frequency * (self.k1 + 1.0) / (frequency + self.k1 * length_norm))
+                    })
+                    .sum();
+                (document.agent_id.clone(), score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+
+        results.sort_by(|a, b| b.1.total_cmp(&a.1));
+        results
+    }
+}
+
+/// Searchable text of an agent. Capabilities and the description are the
+/// most deliberate summary, so they count twice.
+fn document_text(agent: &AgentConfig) -> String {
+    let mut parts: Vec<&str> = vec![&agent.id, &agent.name];
+    for _ in 0..2 {
+        parts.extend(agent.capabilities.iter().map(String::as_str));
+        parts.extend(agent.description.as_deref());
+    }
+    parts.extend(agent.category.as_deref());
+
+    if let Some(persona) = &agent.persona {
+        parts.extend(persona.use_cases.iter().map(String::as_str));
+        parts.push(&persona.system_prompt);
+    }
+
+    parts.join(" ")
+}
+
+/// Lowercase words of two or more letters, without stopwords, with a plain
+/// plural `s` dropped so "tests" finds "test"
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|token| token.chars().count() >= 2 && !STOPWORDS.contains(&token.as_str()))
+        .map(|token| {
+            if token.len() > 3 && token.ends_with('s') && !token.ends_with("ss") {
+                token[..token.len() - 1].to_string()
+            } else {
+                token
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentPersona;
+
+    fn agent(id: &str, capabilities: &[&str], description: &str) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            description: Some(description.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Write the unit-tests for my Parser, please!"),
+            vec!["write", "unit", "test", "parser"]
+        );
+        assert_eq!(tokenize("class access"), vec!["class", "access"]);
+    }
+
+    #[test]
+    fn test_search_ranks_best_lexical_match() {
+        let mut reviewer = agent("pr-reviewer", &["review"], "Reviews pull requests for bugs");
+        reviewer.persona = Some(AgentPersona {
+            system_prompt: "You read diffs and leave review comments on style and correctness.".to_string(),
+            ..Default::default()
+        });
+        let agents = [
+            agent("docbot", &["documentation"], "Writes README files and API documentation"),
+            reviewer,
+            agent("tester", &["testing"], "Writes unit tests and finds flaky tests"),
+        ];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+        let index = Bm25Index::build(&refs, &SemanticRoutingConfig::default());
+
+        let ids = |prompt: &str| -> Vec<String> {
+            index.search(prompt).into_iter().map(|(id, _)| id).collect()
+        };
+
+        assert_eq!(ids("leave comments on this diff"), vec!["pr-reviewer"]);
+        assert_eq!(ids("the tests are flaky")[0], "tester");
+        assert_eq!(ids("update the README documentation")[0], "docbot");
+        assert!(ids("the and of").is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//! Load-balancing strategies for choosing among a rule's preferred agents
+
+use crate::config::{AgentConfig, LoadBalancingStrategy};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Runtime state of an agent used by the strategies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentLoad {
+    pub active_tasks: usize,
+    pub has_quota: bool,
+}
+
+impl Default for AgentLoad {
+    fn default() -> Self {
+        Self {
+            active_tasks: 0,
+            has_quota: true,
+        }
+    }
+}
+
+/// Snapshot of every agent's load, keyed by agent ID
+pub type AgentLoads = HashMap<String, AgentLoad>;
+
+pub struct LoadBalancer {
+    strategy: LoadBalancingStrategy,
+    /// Round-robin cursor per candidate group
+    cursors: Mutex<HashMap<String, usize>>,
+}
+
+impl LoadBalancer {
+    pub fn new(strategy: LoadBalancingStrategy) -> Self {
+        Self {
+            strategy,
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn strategy(&self) -> LoadBalancingStrategy {
+        self.strategy
+    }
+
+    /// Order candidates best-first according to the strategy.
+    /// Agents that are out of quota are dropped.
+    pub fn rank<'a>(
+        &self,
+        candidates: &[&'a AgentConfig],
+        loads: &AgentLoads,
+    ) -> Vec<&'a AgentConfig> {
+        self.rank_with(candidates, loads, true)
+    }
+
+    /// Same order `rank` would return, without advancing the round-robin cursor
+    pub fn preview<'a>(
+        &self,
+        candidates: &[&'a AgentConfig],
+        loads: &AgentLoads,
+    ) -> Vec<&'a AgentConfig> {
+        self.rank_with(candidates, loads, false)
+    }
+
+    fn rank_with<'a>(
+        &self,
+        candidates: &[&'a AgentConfig],
+        loads: &AgentLoads,
+        advance: bool,
+    ) -> Vec<&'a AgentConfig> {
+        let load_of = |agent: &AgentConfig| loads.get(&agent.id).cloned().unwrap_or_default();
+
+        let mut ranked: Vec<&'a AgentConfig> = candidates
+            .iter()
+            .copied()
+            .filter(|agent| load_of(agent).has_quota)
+            .collect();
+
+        if ranked.len() < 2 {
+            return ranked;
+        }
+
+        match self.strategy {
+            LoadBalancingStrategy::FirstWithQuota => {}
+            LoadBalancingStrategy::RoundRobin => {
+                let offset = self.next_cursor(candidates, advance) % ranked.len();
+                ranked.rotate_left(offset);
+            }
+            LoadBalancingStrategy::LeastLoaded => {
+                // Stable sort keeps rule order between equally loaded agents
+                ranked.sort_by_key(|agent| load_of(agent).active_tasks);
+            }
+            LoadBalancingStrategy::WeightedRandom => {
+                ranked = Self::weighted_shuffle(ranked);
+            }
+        }
+
+        ranked
+    }
+
+    fn next_cursor(&self, candidates: &[&AgentConfig], advance: bool) -> usize {
+        let key = candidates
+            .iter()
+            .map(|agent| agent.id.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut cursors = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = cursors.entry(key).or_insert(0);
+        let current = *cursor;
+        if advance {
+            *cursor = cursor.wrapping_add(1);
+        }
+        current
+    }
+
+    /// Sample without replacement, weighting each agent by `priority + 1`
+    fn weighted_shuffle(mut pool: Vec<&AgentConfig>) -> Vec<&AgentConfig> {
+        let mut rng = rand::rng();
+        let mut ordered = Vec::with_capacity(pool.len());
+
+        while !pool.is_empty() {
+            let total: u32 = pool.iter().map(|agent| agent.priority as u32 + 1).sum();
+            let mut roll = rng.random_range(0..total);
+
+            let index = pool
+                .iter()
+                .position(|agent| {
+                    let weight = agent.priority as u32 + 1;
+                    if roll < weight {
+                        true
+                    } else {
+                        roll -= weight;
+                        false
+                    }
+                })
+                .unwrap_or(0);
+
+            ordered.push(pool.remove(index));
+        }
+
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RateLimit;
+
+    fn create_test_agent(id: &str, priority: u8) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: "cli".to_string(),
+            command: Some("test".to_string()),
+            args: None,
+            extension_name: None,
+            rate_limit: RateLimit::default(),
+            capabilities: vec![],
+            priority,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn ids(agents: &[&AgentConfig]) -> Vec<String> {
+        agents.iter().map(|a| a.id.clone()).collect()
+    }
+
+    #[test]
+    fn test_round_robin_rotates() {
+        let agents = [create_test_agent("a", 1), create_test_agent("b", 1)];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+        let balancer = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let loads = AgentLoads::new();
+
+        let picks: Vec<String> = (0..4)
+            .map(|_| balancer.rank(&refs, &loads)[0].id.clone())
+            .collect();
+        assert_eq!(picks, vec!["a", "b", "a", "b"]);
+
+        // Previewing shows the next pick without consuming it
+        assert_eq!(balancer.preview(&refs, &loads)[0].id, "a");
+        assert_eq!(balancer.preview(&refs, &loads)[0].id, "a");
+        assert_eq!(balancer.rank(&refs, &loads)[0].id, "a");
+    }
+
+    #[test]
+    fn test_out_of_quota_agents_are_skipped() {
+        let agents = [create_test_agent("a", 1), create_test_agent("b", 1)];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+        let loads = AgentLoads::from([(
+            "a".to_string(),
+            AgentLoad { active_tasks: 0, has_quota: false },
+        )]);
+
+        for strategy in [
+            LoadBalancingStrategy::RoundRobin,
+            LoadBalancingStrategy::LeastLoaded,
+            LoadBalancingStrategy::WeightedRandom,
+            LoadBalancingStrategy::FirstWithQuota,
+        ] {
+            let balancer = LoadBalancer::new(strategy);
+            for _ in 0..3 {
+                assert_eq!(ids(&balancer.rank(&refs, &loads)), vec!["b"], "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_least_loaded_prefers_idle_agent() {
+        let agents = [create_test_agent("busy", 1), create_test_agent("idle", 1)];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+        let loads = AgentLoads::from([
+            ("busy".to_string(), AgentLoad { active_tasks: 3, has_quota: true }),
+            ("idle".to_string(), AgentLoad { active_tasks: 1, has_quota: true }),
+        ]);
+
+        let balancer = LoadBalancer::new(LoadBalancingStrategy::LeastLoaded);
+        assert_eq!(ids(&balancer.rank(&refs, &loads)), vec!["idle", "busy"]);
+    }
+
+    #[test]
+    fn test_first_with_quota_keeps_rule_order() {
+        let agents = [create_test_agent("a", 1), create_test_agent("b", 200)];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let balancer = LoadBalancer::new(LoadBalancingStrategy::FirstWithQuota);
+        assert_eq!(ids(&balancer.rank(&refs, &AgentLoads::new())), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_weighted_random_returns_every_candidate() {
+        let agents = [create_test_agent("a", 0), create_test_agent("b", 255)];
+        let refs: Vec<&AgentConfig> = agents.iter().collect();
+
+        let balancer = LoadBalancer::new(LoadBalancingStrategy::WeightedRandom);
+        let mut ranked = ids(&balancer.rank(&refs, &AgentLoads::new()));
+        ranked.sort();
+        assert_eq!(ranked, vec!["a", "b"]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config_interpolation::interpolate_table;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -12,6 +13,13 @@ pub struct Config {
     pub routing: RoutingConfig,
     pub rate_limiting: RateLimitingConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub agent_library: AgentLibraryConfig,
+    #[serde(default)]
+    pub commands: CommandsConfig,
+    /// Files this config was loaded from (watched for hot-reload)
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -32,7 +40,7 @@ pub struct MainAgentConfig {
     pub session_token_path: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct AgentConfig {
     pub id: String,
     pub name: String,
@@ -46,8 +54,80 @@ pub struct AgentConfig {
     pub capabilities: Vec<String>,
     #[serde(default)]
     pub priority: u8,
-    #[serde(default)]
+    /// Whether routing, failover and `list_agents` use the agent (on if unset)
+    #[serde(default = "default_true")]
     pub enabled: bool,
+    /// Max tasks running on this agent at once (unbounded if unset)
+    #[serde(default)]
+    pub max_concurrent_tasks: Option<usize>,
+    /// Execution deadline in seconds (no deadline if unset)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Retry transient failures (a single attempt if unset)
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+    /// What the agent is good at
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Library category (engineering, utility, creative, ...)
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Persona of a `prompt` agent loaded from the agent library
+    #[serde(skip)]
+    pub persona: Option<AgentPersona>,
+    /// Extra environment variables for the agent process
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Inherited variables to drop from the agent process
+    #[serde(default)]
+    pub env_remove: Vec<String>,
+    /// Start the agent process without any inherited variables. Off by
+    /// default: the agent then sees the server's whole environment
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Working directory of the agent process (server's cwd if unset)
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// `KEY=VALUE` file read on every spawn and added to the environment
+    #[serde(default)]
+    pub secrets_from_file: Option<String>,
+    /// `command` and `args` as written in the config file, before `${VAR}`
+    /// interpolation put values (possibly secrets) into them
+    #[serde(skip)]
+    pub as_written: Option<LaunchTemplate>,
+}
+
+/// Launch strings of an agent before interpolation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LaunchTemplate {
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+}
+
+/// System prompt and metadata of an agent library persona
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AgentPersona {
+    /// Markdown body of the persona file
+    pub system_prompt: String,
+    pub tools: Vec<String>,
+    pub color: Option<String>,
+    pub use_cases: Vec<String>,
+    /// File the persona was read from
+    pub source: PathBuf,
+    pub origin: AgentOrigin,
+    /// CLI agent that executes the persona
+    pub runner: Option<String>,
+}
+
+/// Where a library agent came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgentOrigin {
+    #[default]
+    BuiltIn,
+    Custom,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -70,37 +150,261 @@ impl Default for RateLimit {
     }
 }
 
+/// Retry policy for transient agent failures
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the delay between attempts
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Growth factor of the delay after each attempt
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    /// Error classes worth another attempt
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryableError>,
+}
+
+fn default_max_attempts() -> u32 { 3 }
+fn default_initial_backoff_ms() -> u64 { 500 }
+fn default_max_backoff_ms() -> u64 { 30_000 }
+fn default_backoff_multiplier() -> f64 { 2.0 }
+fn default_retry_on() -> Vec<RetryableError> {
+    vec![
+        RetryableError::SpawnFailed,
+        RetryableError::ProcessExit,
+        RetryableError::MalformedResponse,
+    ]
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            backoff_multiplier: default_backoff_multiplier(),
+            retry_on: default_retry_on(),
+        }
+    }
+}
+
+/// Classes of agent failure a retry policy can opt into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryableError {
+    /// The agent process could not be started
+    SpawnFailed,
+    /// The agent process exited with a non-zero status
+    ProcessExit,
+    /// The agent answered with something that is not valid JSON-RPC
+    MalformedResponse,
+    /// The agent answered with a JSON-RPC error
+    AgentError,
+    /// The attempt ran past its deadline
+    Timeout,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct RoutingConfig {
     #[serde(default)]
     pub tier: RoutingTier,
     pub rules: Vec<RoutingRule>,
+    /// How rules and agents are combined into a choice
+    #[serde(default)]
+    pub mode: RoutingMode,
+    /// Weights of the `scored` mode
+    #[serde(default)]
+    pub weights: ScoringWeights,
+    /// BM25 matching of prompts against agent descriptions
+    #[serde(default)]
+    pub semantic: SemanticRoutingConfig,
+    /// Routing decision cache and sticky sessions
+    #[serde(default)]
+    pub cache: RoutingCacheConfig,
 }
 
-/// Routing tier determines rule priority
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+/// Reuse of routing decisions. Sticky sessions apply whenever a task carries
+/// a `session_id`; the decision cache only when `enabled`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RoutingCacheConfig {
+    /// Cache rule and BM25 matches by task type, normalized prompt and context
+    pub enabled: bool,
+    /// Entries kept in each cache before the least recently used is evicted
+    pub capacity: usize,
+    /// Seconds a cached match is reused
+    pub ttl_secs: u64,
+    /// Seconds a session keeps its agent after its last task
+    pub session_ttl_secs: u64,
+}
+
+impl Default for RoutingCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 256,
+            ttl_secs: 300,
+            session_ttl_secs: 1800,
+        }
+    }
+}
+
+/// Local BM25 routing stage over agent names, capabilities, descriptions
+/// and library personas
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SemanticRoutingConfig {
+    pub enabled: bool,
+    /// Where BM25 matches are tried
+    pub stage: SemanticStage,
+    /// Matches tried per task
+    pub top_k: usize,
+    /// Matches scoring below this are ignored
+    pub min_score: f64,
+    /// BM25 term-frequency saturation
+    pub k1: f64,
+    /// BM25 length normalisation (0-1)
+    pub b: f64,
+}
+
+impl Default for SemanticRoutingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stage: SemanticStage::Fallback,
+            top_k: 3,
+            min_score: 0.5,
+            k1: 1.2,
+            b: 0.75,
+        }
+    }
+}
+
+/// Position of the BM25 stage among the routing rules
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub enum RoutingTier {
-    Default,
-    User,
+pub enum SemanticStage {
+    /// After every rule, before the agent priority fallback
+    #[default]
+    Fallback,
+    /// Right after the admin-tier rules
     Admin,
+    /// Right after the user-tier rules
+    User,
+    /// Right after the default-tier rules (same as `fallback`)
+    Default,
+}
+
+impl SemanticStage {
+    /// Tier whose rules run before the stage (`None` = after all rules)
+    pub fn after_tier(self) -> Option<RoutingTier> {
+        match self {
+            Self::Fallback => None,
+            Self::Admin => Some(RoutingTier::Admin),
+            Self::User => Some(RoutingTier::User),
+            Self::Default => Some(RoutingTier::Default),
+        }
+    }
+}
+
+/// How the router turns matching rules into a ranking
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoutingMode {
+    /// First matching rule (by tier, then priority) with an agent that has quota
+    #[default]
+    FirstMatch,
+    /// Weighted score per agent, highest first
+    Scored,
 }
 
-impl Default for RoutingTier {
+/// Score = keyword_hits × matched keywords
+///       + capability_overlap × whether the agent has the `task_type` capability
+///       + rule_priority × best matching rule's rank (0-1, tier included)
+///       + agent_priority × agent priority (0-1)
+///       + semantic × BM25 score relative to the best match (0-1, if enabled)
+///       − load × active tasks
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ScoringWeights {
+    pub keyword_hits: f64,
+    pub capability_overlap: f64,
+    pub rule_priority: f64,
+    pub agent_priority: f64,
+    pub semantic: f64,
+    pub load: f64,
+}
+
+impl Default for ScoringWeights {
     fn default() -> Self {
-        RoutingTier::Default
+        Self {
+            keyword_hits: 1.0,
+            capability_overlap: 0.5,
+            rule_priority: 2.0,
+            agent_priority: 1.0,
+            semantic: 1.0,
+            load: 0.5,
+        }
+    }
+}
+
+impl ScoringWeights {
+    fn named(&self) -> [(&'static str, f64); 6] {
+        [
+            ("keyword_hits", self.keyword_hits),
+            ("capability_overlap", self.capability_overlap),
+            ("rule_priority", self.rule_priority),
+            ("agent_priority", self.agent_priority),
+            ("semantic", self.semantic),
+            ("load", self.load),
+        ]
     }
 }
 
+/// Routing tier determines rule priority
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingTier {
+    #[default]
+    Default,
+    User,
+    Admin,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RoutingRule {
     pub task_type: String,
+    /// Matches every prompt of the task type when empty
     pub keywords: Vec<String>,
+    /// How `keywords`, `all_of`, `any_of` and `exclude_keywords` are matched
+    #[serde(default)]
+    pub match_mode: KeywordMatchMode,
+    /// Every one of these must match
+    #[serde(default)]
+    pub all_of: Vec<String>,
+    /// At least one of these must match (in addition to `keywords`)
+    #[serde(default)]
+    pub any_of: Vec<String>,
+    /// The rule is skipped when any of these match
+    #[serde(default)]
+    pub exclude_keywords: Vec<String>,
+    /// Predicates on `delegate_task`'s `context` JSON; all must hold
+    #[serde(default)]
+    pub context: Vec<ContextCondition>,
     pub preferred_agents: Vec<String>,
     #[serde(default)]
     pub priority: u16,  // 0-999
     #[serde(default)]
     pub enabled: bool,
+    /// Tier of the file the rule came from (`None` = this config's `routing.tier`)
+    #[serde(skip)]
+    pub tier: Option<RoutingTier>,
 }
 
 impl Default for RoutingRule {
@@ -108,25 +412,182 @@ impl Default for RoutingRule {
         Self {
             task_type: String::new(),
             keywords: Vec::new(),
+            match_mode: KeywordMatchMode::default(),
+            all_of: Vec::new(),
+            any_of: Vec::new(),
+            exclude_keywords: Vec::new(),
+            context: Vec::new(),
             preferred_agents: Vec::new(),
             priority: 0,
             enabled: true,
+            tier: None,
+        }
+    }
+}
+
+impl RoutingRule {
+    /// Whether the rule looks at the prompt or context at all, or takes every
+    /// task of its type
+    pub fn has_conditions(&self) -> bool {
+        !(self.keywords.is_empty()
+            && self.all_of.is_empty()
+            && self.any_of.is_empty()
+            && self.exclude_keywords.is_empty()
+            && self.context.is_empty())
+    }
+
+    /// Every pattern of the rule, in all groups
+    pub fn patterns(&self) -> impl Iterator<Item = &String> {
+        self.keywords
+            .iter()
+            .chain(&self.all_of)
+            .chain(&self.any_of)
+            .chain(&self.exclude_keywords)
+    }
+}
+
+/// Predicate on the value at a JSON pointer (RFC 6901) into the task context.
+/// Exactly one operator is set; a missing value never matches.
+///
+/// ```toml
+/// context = [
+///   { pointer = "/language", equals = "rust" },
+///   { pointer = "/repo", glob = "github.com/acme/*" },
+///   { pointer = "/file_count", gt = 50 },
+/// ]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ContextCondition {
+    pub pointer: String,
+    /// Equal to this JSON value (numbers compare by value)
+    #[serde(default)]
+    pub equals: Option<serde_json::Value>,
+    /// String matching this glob (`*` any run of characters, `?` one character)
+    #[serde(default)]
+    pub glob: Option<String>,
+    /// Number greater than this
+    #[serde(default)]
+    pub gt: Option<f64>,
+    /// Number less than this
+    #[serde(default)]
+    pub lt: Option<f64>,
+}
+
+impl ContextCondition {
+    fn operator_count(&self) -> usize {
+        [self.equals.is_some(), self.glob.is_some(), self.gt.is_some(), self.lt.is_some()]
+            .into_iter()
+            .filter(|set| *set)
+            .count()
+    }
+}
+
+impl std::fmt::Display for ContextCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(value) = &self.equals {
+            write!(f, "{} == {}", self.pointer, value)
+        } else if let Some(glob) = &self.glob {
+            write!(f, "{} matches {:?}", self.pointer, glob)
+        } else if let Some(gt) = self.gt {
+            write!(f, "{} > {}", self.pointer, gt)
+        } else if let Some(lt) = self.lt {
+            write!(f, "{} < {}", self.pointer, lt)
+        } else {
+            write!(f, "{} (no operator)", self.pointer)
         }
     }
 }
 
+/// How routing keywords are compared with the prompt (always case-insensitive)
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeywordMatchMode {
+    /// Anywhere in the prompt ("test" matches "latest")
+    #[default]
+    Substring,
+    /// Only as a whole word ("test" matches "run the test" but not "latest")
+    WholeWord,
+    /// Each keyword is a regular expression
+    Regex,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RateLimitingConfig {
-    #[serde(default = "default_strategy")]
-    pub strategy: String,
+    #[serde(default)]
+    pub strategy: LoadBalancingStrategy,
     #[serde(default = "default_true")]
     pub track_usage: bool,
     pub usage_db_path: String,
 }
 
-fn default_strategy() -> String { "round-robin".to_string() }
 fn default_true() -> bool { true }
 
+/// How to pick among several preferred agents of a routing rule
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadBalancingStrategy {
+    /// Rotate through the candidates on every request
+    #[default]
+    RoundRobin,
+    /// Prefer the candidate with the fewest active tasks
+    LeastLoaded,
+    /// Random pick weighted by agent priority
+    WeightedRandom,
+    /// First candidate in rule order that still has quota
+    FirstWithQuota,
+}
+
+/// Agent persona library: `agents/*.md` + `agents/agents.json`,
+/// overlaid with `custom/agents.json`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AgentLibraryConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_library_path")]
+    pub path: String,
+    #[serde(default = "default_custom_library_path")]
+    pub custom_path: String,
+    /// ID of the CLI agent that runs library personas
+    #[serde(default)]
+    pub runner: Option<String>,
+}
+
+fn default_library_path() -> String { "agents".to_string() }
+fn default_custom_library_path() -> String { "custom".to_string() }
+
+impl Default for AgentLibraryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_library_path(),
+            custom_path: default_custom_library_path(),
+            runner: None,
+        }
+    }
+}
+
+/// Gemini extension commands (`commands/**/*.toml`), served as MCP prompts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommandsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// `${extensionPath}` in prompts is the parent of this directory
+    #[serde(default = "default_commands_path")]
+    pub path: String,
+}
+
+fn default_commands_path() -> String { "commands".to_string() }
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_commands_path(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LoggingConfig {
     #[serde(default = "default_log_level")]
@@ -138,57 +599,332 @@ pub struct LoggingConfig {
 fn default_log_level() -> String { "info".to_string() }
 fn default_output() -> String { "stdout".to_string() }
 
+/// Recursively overlay `overlay` onto `base`; non-table values are replaced
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Resolve the current user's home directory
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Expand a leading `~` in a path to the user's home directory
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" {
+        return home_dir().unwrap_or_else(|| PathBuf::from(path));
+    }
+
+    if let Some(rest) = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+        if let Some(home) = home_dir() {
+            return home.join(rest);
+        }
+    }
+
+    PathBuf::from(path)
+}
+
+/// Directory keys that point at files shipped with the extension
+const BUNDLED_PATH_KEYS: &[(&str, &str)] = &[
+    ("agent_library", "path"),
+    ("agent_library", "custom_path"),
+    ("commands", "path"),
+];
+
+/// Root of the installed extension: the nearest directory above the
+/// executable (e.g. `<root>/target/release/`) holding `gemini-extension.json`
+pub fn extension_root() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    exe.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("gemini-extension.json").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Join a relative path onto `base`; absolute and `~` paths are kept as is
+fn resolve_against(base: &Path, path: &str) -> String {
+    if path.starts_with('~') || Path::new(path).is_absolute() {
+        return path.to_string();
+    }
+    base.join(path).to_string_lossy().into_owned()
+}
+
+/// Make the relative bundled paths set in a config file relative to that
+/// file, not to whatever directory the server was started from
+fn resolve_file_paths(table: &mut toml::Table, file: &Path) {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+
+    for (section, key) in BUNDLED_PATH_KEYS {
+        if let Some(toml::Value::Table(section)) = table.get_mut(*section) {
+            if let Some(toml::Value::String(value)) = section.get_mut(*key) {
+                *value = resolve_against(&dir, value);
+            }
+        }
+    }
+}
+
+/// `command` and `args` of each `[[agents]]` entry, read before interpolation
+fn launch_templates(table: &toml::Table) -> Vec<LaunchTemplate> {
+    let Some(toml::Value::Array(agents)) = table.get("agents") else {
+        return Vec::new();
+    };
+    agents
+        .iter()
+        .map(|agent| agent.clone().try_into().unwrap_or_default())
+        .collect()
+}
+
+/// Attach the templates read by `launch_templates` to the parsed agents
+fn attach_launch_templates(agents: &mut [AgentConfig], templates: Vec<LaunchTemplate>) {
+    for (agent, template) in agents.iter_mut().zip(templates) {
+        agent.as_written = Some(template);
+    }
+}
+
+/// A config file in the layered setup, lowest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigLayer {
+    /// `./.bl1nk-agents-manager.toml` of the current project
+    Project,
+    /// `~/.config/bl1nk-agents-manager/config.toml`
+    User,
+    /// Machine-wide file managed by an administrator
+    System,
+}
+
+impl ConfigLayer {
+    /// Highest tier the layer's rules can have, and the tier they get by default
+    pub fn tier(self) -> RoutingTier {
+        match self {
+            ConfigLayer::Project => RoutingTier::Default,
+            ConfigLayer::User => RoutingTier::User,
+            ConfigLayer::System => RoutingTier::Admin,
+        }
+    }
+
+    /// Candidate files for the layer; the first one that exists is used
+    fn candidate_paths(self) -> Vec<PathBuf> {
+        match self {
+            ConfigLayer::Project => vec![
+                PathBuf::from("./.bl1nk-agents-manager.toml"),
+                PathBuf::from("./config.toml"),
+            ],
+            ConfigLayer::User => home_dir()
+                .map(|home| vec![
+                    home.join(".config/bl1nk-agents-manager/config.toml"),
+                    home.join(".bl1nk-agents-manager.toml"),
+                ])
+                .unwrap_or_default(),
+            ConfigLayer::System => match std::env::var_os("BL1NK_SYSTEM_CONFIG") {
+                Some(path) => vec![PathBuf::from(path)],
+                None => vec![system_config_dir().join("config.toml")],
+            },
+        }
+    }
+}
+
+#[cfg(unix)]
+fn system_config_dir() -> PathBuf {
+    PathBuf::from("/etc/bl1nk-agents-manager")
+}
+
+#[cfg(not(unix))]
+fn system_config_dir() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
+        .join("bl1nk-agents-manager")
+}
+
 impl Config {
     /// Load config from file path
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let config = Self::load_unvalidated(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Like `load`, but without the final validation, for tools that
+    /// want to report every problem themselves
+    pub fn load_unvalidated<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path.as_ref())
             .with_context(|| format!("Failed to read config file: {:?}", path.as_ref()))?;
-        
-        let mut config: Config = toml::from_str(&content)
+
+        let mut table: toml::Table = toml::from_str(&content)
             .context("Failed to parse TOML config")?;
-        
-        // Inject bundled PMAT agent if feature is enabled
-        #[cfg(feature = "bundle-pmat")]
-        {
-            config.inject_bundled_pmat();
-        }
-        
-        config.validate()?;
+        let templates = launch_templates(&table);
+        interpolate_table(&mut table)?;
+        resolve_file_paths(&mut table, path.as_ref());
+
+        let mut config: Config = toml::Value::Table(table)
+            .try_into()
+            .context("Failed to parse TOML config")?;
+        attach_launch_templates(&mut config.agents, templates);
+        config.sources = vec![path.as_ref().to_path_buf()];
+        config.resolve_bundled_paths();
+        config.add_builtin_agents();
+
         Ok(config)
     }
 
-    /// Load config from default locations
+    /// Load and merge the system, user and project config files.
+    ///
+    /// Sections and agents from higher layers (System > User > Project) win;
+    /// routing rules from every layer are kept, each with its layer's tier.
     pub fn load_default() -> Result<Self> {
-        let config_paths = Self::default_config_paths();
-        
-        for path in config_paths {
-            if path.exists() {
-                tracing::info!("Loading config from: {:?}", path);
-                return Self::load(&path);
+        let config = Self::load_default_unvalidated()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `load_default` without the final validation
+    pub fn load_default_unvalidated() -> Result<Self> {
+        let mut layers = Vec::new();
+        let mut candidates = Vec::new();
+
+        for layer in [ConfigLayer::Project, ConfigLayer::User, ConfigLayer::System] {
+            let paths = layer.candidate_paths();
+            if let Some(path) = paths.iter().find(|path| path.exists()) {
+                tracing::info!("Loading {:?} config from: {:?}", layer, path);
+                layers.push((layer, path.clone()));
             }
+            candidates.extend(paths);
         }
-        
-        anyhow::bail!(
-            "No config file found. Create ~/.config/bl1nk-agents-manager/config.toml or use --config"
-        );
-    }
-
-    /// Default config file locations (in order of priority)
-    fn default_config_paths() -> Vec<PathBuf> {
-        let mut paths = Vec::new();
-        
-        // Current directory
-        paths.push(PathBuf::from("./config.toml"));
-        paths.push(PathBuf::from("./.bl1nk-agents-manager.toml"));
-        
-        // User config directory
-        if let Ok(home) = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
-            let home_path = PathBuf::from(home);
-            paths.push(home_path.join(".config/bl1nk-agents-manager/config.toml"));
-            paths.push(home_path.join(".bl1nk-agents-manager.toml"));
-        }
-        
-        paths
+
+        if layers.is_empty() {
+            anyhow::bail!(
+                "No config file found. Create ~/.config/bl1nk-agents-manager/config.toml or use --config"
+            );
+        }
+
+        let mut config = Self::merge_layers(&layers)?;
+        // Watch every candidate so a layer created later is picked up on reload
+        config.sources = candidates;
+        config.resolve_bundled_paths();
+        config.add_builtin_agents();
+
+        Ok(config)
+    }
+
+    /// Merge layer files (given lowest precedence first) into one config
+    pub fn merge_layers(layers: &[(ConfigLayer, PathBuf)]) -> Result<Self> {
+        let mut merged = toml::Table::new();
+        let mut agents: Vec<(ConfigLayer, AgentConfig)> = Vec::new();
+        let mut rules = Vec::new();
+
+        for (layer, path) in layers {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file: {:?}", path))?;
+            let mut table: toml::Table = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse TOML config: {:?}", path))?;
+            let templates = launch_templates(&table);
+            interpolate_table(&mut table)
+                .with_context(|| format!("Invalid config file: {:?}", path))?;
+            resolve_file_paths(&mut table, path);
+
+            // Agents and rules are merged by hand, everything else key by key
+            let mut layer_agents: Vec<AgentConfig> = match table.remove("agents") {
+                Some(value) => value.try_into()
+                    .with_context(|| format!("Invalid [[agents]] in {:?}", path))?,
+                None => Vec::new(),
+            };
+            attach_launch_templates(&mut layer_agents, templates);
+
+            let mut layer_rules: Vec<RoutingRule> = Vec::new();
+            let mut declared_tier = None;
+            if let Some(toml::Value::Table(routing)) = table.get_mut("routing") {
+                if let Some(value) = routing.remove("rules") {
+                    layer_rules = value.try_into()
+                        .with_context(|| format!("Invalid [[routing.rules]] in {:?}", path))?;
+                }
+                if let Some(value) = routing.remove("tier") {
+                    declared_tier = Some(value.try_into::<RoutingTier>()
+                        .with_context(|| format!("Invalid routing.tier in {:?}", path))?);
+                }
+            }
+
+            // A file may lower its own tier but never raise it above its layer
+            let tier = declared_tier.map_or(layer.tier(), |tier| tier.min(layer.tier()));
+            for mut rule in layer_rules {
+                rule.tier = Some(tier.clone());
+                rules.push(rule);
+            }
+
+            for agent in layer_agents {
+                if let Some(existing) = agents.iter_mut().find(|(_, a)| a.id == agent.id) {
+                    tracing::info!(
+                        "Agent '{}' from the {:?} config overrides the {:?} definition",
+                        agent.id, layer, existing.0
+                    );
+                    *existing = (*layer, agent);
+                } else {
+                    agents.push((*layer, agent));
+                }
+            }
+
+            merge_tables(&mut merged, table);
+        }
+
+        merged.insert("agents".to_string(), toml::Value::Array(Vec::new()));
+        let routing = merged
+            .entry("routing")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let toml::Value::Table(routing) = routing {
+            routing.insert("rules".to_string(), toml::Value::Array(Vec::new()));
+        }
+
+        let mut config: Config = toml::Value::Table(merged)
+            .try_into()
+            .context("Failed to parse merged config")?;
+        config.agents = agents.into_iter().map(|(_, agent)| agent).collect();
+        config.routing.rules = rules;
+        config.routing.tier = layers
+            .iter()
+            .map(|(layer, _)| layer.tier())
+            .max()
+            .unwrap_or_default();
+        config.sources = layers.iter().map(|(_, path)| path.clone()).collect();
+
+        Ok(config)
+    }
+
+    /// Bundled paths no config file set still hold their relative defaults;
+    /// those live in the extension root
+    fn resolve_bundled_paths(&mut self) {
+        let Some(root) = extension_root() else {
+            return;
+        };
+        for path in [
+            &mut self.agent_library.path,
+            &mut self.agent_library.custom_path,
+            &mut self.commands.path,
+        ] {
+            *path = resolve_against(&root, path);
+        }
+    }
+
+    /// Agents that come with the program rather than from config files
+    fn add_builtin_agents(&mut self) {
+        // Inject bundled PMAT agent if feature is enabled
+        #[cfg(feature = "bundle-pmat")]
+        {
+            self.inject_bundled_pmat();
+        }
+
+        self.inject_library_agents();
     }
 
     /// Inject bundled PMAT agent
@@ -212,6 +948,7 @@ impl Config {
             ],
             priority: 200,
             enabled: true,
+            ..Default::default()
         };
 
         // Check if already exists
@@ -221,69 +958,175 @@ impl Config {
         }
     }
 
+    /// Everything hot-reload watches: the config's source files and, when
+    /// enabled, the agent library
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.sources.clone();
+        if self.agent_library.enabled {
+            paths.extend(crate::agents::library::watched_paths(&self.agent_library));
+        }
+        paths
+    }
+
+    /// Register agent library personas next to the configured agents.
+    /// Agents from the config file win on ID clashes.
+    fn inject_library_agents(&mut self) {
+        if !self.agent_library.enabled {
+            return;
+        }
+
+        let mut added = 0;
+        for agent in crate::agents::library::load(&self.agent_library) {
+            if self.agents.iter().any(|a| a.id == agent.id) {
+                tracing::debug!("Library agent '{}' is shadowed by a configured agent", agent.id);
+                continue;
+            }
+            self.agents.push(agent);
+            added += 1;
+        }
+
+        if added > 0 {
+            tracing::info!("📚 Loaded {} agents from the agent library", added);
+        }
+    }
+
     /// Validate configuration
     fn validate(&self) -> Result<()> {
+        // Routing rules may name agents that are only configured on some machines
+        let agent_ids: Vec<&str> = self.agents.iter().map(|a| a.id.as_str()).collect();
+        for rule in &self.routing.rules {
+            for preferred_agent in &rule.preferred_agents {
+                if !agent_ids.contains(&preferred_agent.as_str()) {
+                    tracing::warn!(
+                        "⚠️  Routing rule references unknown agent: {} (will be skipped)",
+                        preferred_agent
+                    );
+                }
+            }
+        }
+
+        match self.validation_errors().as_slice() {
+            [] => Ok(()),
+            [error] => anyhow::bail!("{}", error),
+            errors => anyhow::bail!("Invalid config:\n  - {}", errors.join("\n  - ")),
+        }
+    }
+
+    /// Every problem that makes the config unusable, not just the first one
+    pub fn validation_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
         // Validate server config
         if self.server.max_concurrent_tasks == 0 {
-            anyhow::bail!("max_concurrent_tasks must be greater than 0");
+            errors.push("max_concurrent_tasks must be greater than 0".to_string());
         }
 
         // Validate agents
         if self.agents.is_empty() {
-            anyhow::bail!("At least one agent must be configured");
+            errors.push("At least one agent must be configured".to_string());
         }
 
         // Check for duplicate agent IDs
         let mut seen_ids = std::collections::HashSet::new();
         for agent in &self.agents {
             if !seen_ids.insert(&agent.id) {
-                anyhow::bail!("Duplicate agent ID: {}", agent.id);
+                errors.push(format!("Duplicate agent ID: {}", agent.id));
             }
-        }
 
-        // Validate routing rules reference valid agents
-        let agent_ids: Vec<String> = self.agents.iter().map(|a| a.id.clone()).collect();
-        for rule in &self.routing.rules {
-            for preferred_agent in &rule.preferred_agents {
-                if !agent_ids.contains(preferred_agent) {
-                    tracing::warn!(
-                        "⚠️  Routing rule references unknown agent: {} (will be skipped)",
-                        preferred_agent
-                    );
+            if agent.max_concurrent_tasks == Some(0) {
+                errors.push(format!("max_concurrent_tasks must be greater than 0 for agent '{}'", agent.id));
+            }
+
+            if agent.timeout_secs == Some(0) {
+                errors.push(format!("timeout_secs must be greater than 0 for agent '{}'", agent.id));
+            }
+
+            for key in agent.env.keys().chain(&agent.env_remove) {
+                if key.is_empty() || key.contains('=') || key.contains('\0') {
+                    errors.push(format!("Invalid environment variable name {:?} for agent '{}'", key, agent.id));
                 }
             }
+
+            if let Some(retry) = &agent.retry {
+                if retry.max_attempts == 0 {
+                    errors.push(format!("retry.max_attempts must be greater than 0 for agent '{}'", agent.id));
+                }
+                if retry.backoff_multiplier.is_nan() || retry.backoff_multiplier < 1.0 {
+                    errors.push(format!("retry.backoff_multiplier must be at least 1.0 for agent '{}'", agent.id));
+                }
+            }
+        }
+
+        if let Some(runner) = &self.agent_library.runner {
+            match self.agents.iter().find(|a| &a.id == runner) {
+                Some(agent) if agent.agent_type == "cli" => {}
+                Some(_) => errors.push(format!("agent_library.runner '{}' must be a cli agent", runner)),
+                None => errors.push(format!("agent_library.runner references unknown agent: {}", runner)),
+            }
+        }
+
+        for (name, weight) in self.routing.weights.named() {
+            if !weight.is_finite() || weight < 0.0 {
+                errors.push(format!("routing.weights.{} must be a non-negative number, got {}", name, weight));
+            }
+        }
+
+        if self.routing.cache.capacity == 0 {
+            errors.push("routing.cache.capacity must be greater than 0".to_string());
+        }
+
+        let semantic = &self.routing.semantic;
+        if semantic.top_k == 0 {
+            errors.push("routing.semantic.top_k must be greater than 0".to_string());
+        }
+        if !semantic.min_score.is_finite() || semantic.min_score < 0.0 {
+            errors.push(format!("routing.semantic.min_score must be a non-negative number, got {}", semantic.min_score));
+        }
+        if !semantic.k1.is_finite() || semantic.k1 < 0.0 {
+            errors.push(format!("routing.semantic.k1 must be a non-negative number, got {}", semantic.k1));
+        }
+        if !(0.0..=1.0).contains(&semantic.b) {
+            errors.push(format!("routing.semantic.b must be between 0 and 1, got {}", semantic.b));
         }
 
         // Validate priority ranges
         for rule in &self.routing.rules {
             if rule.priority > 999 {
-                anyhow::bail!(
+                errors.push(format!(
                     "Rule priority must be 0-999, got {} for task_type '{}'",
                     rule.priority,
                     rule.task_type
-                );
+                ));
             }
-        }
-
-        Ok(())
-    }
 
-    /// Get agent by ID
-    pub fn get_agent(&self, id: &str) -> Option<&AgentConfig> {
-        self.agents.iter().find(|a| a.id == id && a.enabled)
-    }
+            for condition in &rule.context {
+                if condition.operator_count() != 1 {
+                    errors.push(format!(
+                        "Context condition on {:?} in rule for task_type '{}' needs exactly one of equals, glob, gt, lt",
+                        condition.pointer, rule.task_type
+                    ));
+                }
+                if !condition.pointer.is_empty() && !condition.pointer.starts_with('/') {
+                    errors.push(format!(
+                        "Context pointer {:?} in rule for task_type '{}' must be empty or start with '/'",
+                        condition.pointer, rule.task_type
+                    ));
+                }
+            }
 
-    /// Get agents by capability
-    pub fn get_agents_by_capability(&self, capability: &str) -> Vec<&AgentConfig> {
-        self.agents
-            .iter()
-            .filter(|a| a.enabled && a.capabilities.contains(&capability.to_string()))
-            .collect()
-    }
+            if rule.match_mode == KeywordMatchMode::Regex {
+                for pattern in rule.patterns() {
+                    if let Err(e) = crate::agents::matcher::compile_regex(pattern) {
+                        errors.push(format!(
+                            "Invalid regex {:?} in rule for task_type '{}': {}",
+                            pattern, rule.task_type, e
+                        ));
+                    }
+                }
+            }
+        }
 
-    /// Get enabled agents only
-    pub fn get_enabled_agents(&self) -> Vec<&AgentConfig> {
-        self.agents.iter().filter(|a| a.enabled).collect()
+        errors
     }
 }
 
@@ -322,6 +1165,307 @@ mod tests {
         assert_eq!(config.server.max_concurrent_tasks, 5);
         assert_eq!(config.routing.tier, RoutingTier::Default);
         assert_eq!(config.logging.level, "info");
+        assert_eq!(config.rate_limiting.strategy, LoadBalancingStrategy::RoundRobin);
+    }
+
+    #[test]
+    fn test_strategy_parsing() {
+        let config: RateLimitingConfig = toml::from_str(r#"
+            strategy = "least-loaded"
+            usage_db_path = "/tmp/test.db"
+        "#).unwrap();
+        assert_eq!(config.strategy, LoadBalancingStrategy::LeastLoaded);
+
+        let invalid: Result<RateLimitingConfig, _> = toml::from_str(r#"
+            strategy = "fastest"
+            usage_db_path = "/tmp/test.db"
+        "#);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_invalid_rule_regex_is_rejected() {
+        let config_str = r#"
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [[agents]]
+            id = "test-agent"
+            name = "Test"
+            type = "cli"
+            command = "test"
+            capabilities = ["code"]
+
+            [[routing.rules]]
+            task_type = "code"
+            match_mode = "regex"
+            keywords = ["rust(lang)?"]
+            exclude_keywords = ["wasm[32"]
+            preferred_agents = ["test-agent"]
+
+            [rate_limiting]
+            usage_db_path = "/tmp/test.db"
+
+            [logging]
+        "#;
+
+        let config: Config = toml::from_str(config_str).unwrap();
+        let errors = config.validation_errors();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("wasm[32"));
+    }
+
+    #[test]
+    fn test_rule_context_conditions() {
+        let rule: RoutingRule = toml::from_str(r#"
+            task_type = "code"
+            keywords = []
+            preferred_agents = ["test-agent"]
+            context = [
+                { pointer = "/language", equals = "rust" },
+                { pointer = "/file_count", gt = 50 },
+                { pointer = "repo", glob = "*", lt = 3 },
+            ]
+        "#).unwrap();
+        assert!(rule.has_conditions());
+        assert_eq!(rule.context[0].to_string(), "/language == \"rust\"");
+        assert_eq!(rule.context[1].gt, Some(50.0));
+
+        let mut config: Config = toml::from_str(r#"
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [[agents]]
+            id = "test-agent"
+            name = "Test"
+            type = "cli"
+            command = "test"
+            capabilities = ["code"]
+
+            [routing]
+            rules = []
+
+            [rate_limiting]
+            usage_db_path = "/tmp/test.db"
+
+            [logging]
+        "#).unwrap();
+        config.routing.rules.push(rule);
+
+        let errors = config.validation_errors();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].contains("exactly one of"));
+        assert!(errors[1].contains("must be empty or start with '/'"));
+    }
+
+    #[test]
+    fn test_retry_policy_defaults() {
+        let retry: RetryPolicy = toml::from_str(r#"
+            max_attempts = 5
+            retry_on = ["timeout", "agent-error"]
+        "#).unwrap();
+        assert_eq!(retry.max_attempts, 5);
+        assert_eq!(retry.initial_backoff_ms, 500);
+        assert_eq!(retry.retry_on, vec![RetryableError::Timeout, RetryableError::AgentError]);
+
+        let retry: RetryPolicy = toml::from_str("").unwrap();
+        assert!(retry.retry_on.contains(&RetryableError::SpawnFailed));
+        assert!(!retry.retry_on.contains(&RetryableError::Timeout));
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = home_dir().unwrap();
+        assert_eq!(expand_tilde("~/usage.db"), home.join("usage.db"));
+        assert_eq!(expand_tilde("/tmp/usage.db"), PathBuf::from("/tmp/usage.db"));
+        assert_eq!(expand_tilde("data/~/usage.db"), PathBuf::from("data/~/usage.db"));
+    }
+
+    #[test]
+    fn test_agents_keep_launch_strings_as_written() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("config.toml");
+        fs::write(&path, r#"
+            [[agents]]
+            id = "qwen"
+            name = "Qwen"
+            type = "cli"
+            command = "${BL1NK_TEST_UNSET_CMD:-qwencode}"
+            args = ["--token", "${BL1NK_TEST_UNSET_TOKEN:-sk-secret}"]
+            capabilities = []
+
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [routing]
+            rules = []
+
+            [rate_limiting]
+            usage_db_path = "/tmp/usage.db"
+
+            [logging]
+            level = "info"
+        "#).unwrap();
+
+        let config = Config::load_unvalidated(&path).unwrap();
+        let agent = &config.agents[0];
+        assert_eq!(agent.command.as_deref(), Some("qwencode"));
+        assert_eq!(agent.args, Some(vec!["--token".to_string(), "sk-secret".to_string()]));
+        assert_eq!(agent.as_written, Some(LaunchTemplate {
+            command: Some("${BL1NK_TEST_UNSET_CMD:-qwencode}".to_string()),
+            args: Some(vec!["--token".to_string(), "${BL1NK_TEST_UNSET_TOKEN:-sk-secret}".to_string()]),
+        }));
+    }
+
+    #[test]
+    fn test_library_paths_resolve_against_config_file() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("config.toml");
+        fs::write(&path, r#"
+            agents = []
+
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [routing]
+            rules = []
+
+            [rate_limiting]
+            usage_db_path = "/tmp/usage.db"
+
+            [logging]
+            level = "info"
+
+            [agent_library]
+            path = "personas"
+
+            [commands]
+            path = "prompts/commands"
+        "#).unwrap();
+
+        let config = Config::load_unvalidated(&path).unwrap();
+
+        // Set in the file: next to the file, whatever the working directory
+        assert_eq!(PathBuf::from(&config.agent_library.path), temp.path().join("personas"));
+        assert_eq!(PathBuf::from(&config.commands.path), temp.path().join("prompts/commands"));
+        // Left at the default: the bundled directory of the extension
+        let root = extension_root().unwrap();
+        assert_eq!(PathBuf::from(&config.agent_library.custom_path), root.join("custom"));
+    }
+
+    #[test]
+    fn test_merge_layers() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = temp.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+
+        let project = write("project.toml", r#"
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+            max_concurrent_tasks = 8
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [[agents]]
+            id = "shared"
+            name = "Project Shared"
+            type = "cli"
+            capabilities = []
+
+            [[agents]]
+            id = "project-only"
+            name = "Project Only"
+            type = "cli"
+            capabilities = []
+
+            [routing]
+            [[routing.rules]]
+            task_type = "code"
+            keywords = []
+            preferred_agents = ["project-only"]
+
+            [rate_limiting]
+            usage_db_path = "/tmp/usage.db"
+
+            [logging]
+            level = "debug"
+        "#);
+
+        let user = write("user.toml", r#"
+            [[agents]]
+            id = "shared"
+            name = "User Shared"
+            type = "cli"
+            capabilities = []
+
+            [routing]
+            tier = "admin"
+            [[routing.rules]]
+            task_type = "code"
+            keywords = []
+            preferred_agents = ["shared"]
+        "#);
+
+        let system = write("system.toml", r#"
+            [server]
+            max_concurrent_tasks = 2
+
+            [[routing.rules]]
+            task_type = "code"
+            keywords = []
+            preferred_agents = ["shared"]
+        "#);
+
+        let config = Config::merge_layers(&[
+            (ConfigLayer::Project, project),
+            (ConfigLayer::User, user),
+            (ConfigLayer::System, system),
+        ]).unwrap();
+
+        // Sections merge key by key, higher layers win
+        assert_eq!(config.server.max_concurrent_tasks, 2);
+        assert_eq!(config.server.port, 3000);
+        assert_eq!(config.logging.level, "debug");
+
+        // Overlapping agents: the higher layer's definition wins
+        assert_eq!(config.agents.len(), 2);
+        let shared = config.agents.iter().find(|a| a.id == "shared").unwrap();
+        assert_eq!(shared.name, "User Shared");
+
+        // Each file keeps its tier; a user file cannot claim admin
+        let tiers: Vec<Option<RoutingTier>> = config.routing.rules.iter().map(|r| r.tier.clone()).collect();
+        assert_eq!(tiers, vec![
+            Some(RoutingTier::Default),
+            Some(RoutingTier::User),
+            Some(RoutingTier::Admin),
+        ]);
+        assert_eq!(config.sources.len(), 3);
     }
 
     #[test]
@@ -0,0 +1,219 @@
+//! `~`, `${VAR}`, `${VAR-default}` and `${VAR:-default}` expansion in config
+//! string values, with shell semantics: `-` falls back only when the variable
+//! is unset, `:-` also when it is empty.
+//!
+//! Runs on the parsed TOML tree before it becomes a `Config`, so errors can
+//! name the exact field (`agents[1].args[0]`). `$$` is a literal `$`.
+//! Routing rule keywords and context conditions are left as written, since
+//! `$` is part of their regex and glob syntax.
+
+use crate::config::expand_tilde;
+use anyhow::Result;
+
+/// Rule fields holding keywords, regexes and globs
+const VERBATIM_RULE_FIELDS: &[&str] = &["keywords", "all_of", "any_of", "exclude_keywords", "context"];
+
+/// Expand every string in `table` using the process environment
+pub fn interpolate_table(table: &mut toml::Table) -> Result<()> {
+    interpolate_table_with(table, &|name| std::env::var(name).ok())
+}
+
+/// Expand every string in `table`, reporting all unresolved variables at once
+pub fn interpolate_table_with(
+    table: &mut toml::Table,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    let mut errors = Vec::new();
+    for (key, value) in table.iter_mut() {
+        interpolate_value(value, key.clone(), lookup, &mut errors);
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("Config interpolation failed:\n  - {}", errors.join("\n  - "));
+    }
+    Ok(())
+}
+
+fn interpolate_value(
+    value: &mut toml::Value,
+    path: String,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    errors: &mut Vec<String>,
+) {
+    if is_verbatim(&path) {
+        return;
+    }
+
+    match value {
+        toml::Value::String(s) => match interpolate_str(s, lookup) {
+            Ok(expanded) => *s = expanded,
+            Err(e) => errors.push(format!("{}: {}", path, e)),
+        },
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, format!("{}[{}]", path, index), lookup, errors);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                interpolate_value(item, format!("{}.{}", path, key), lookup, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Expand one value. A leading `~` becomes the home directory; variables
+/// without a default must be set (an empty default is allowed).
+pub fn interpolate_str(
+    input: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut output = String::with_capacity(input.len());
+    let mut missing = Vec::new();
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+            continue;
+        }
+
+        let Some(body) = rest.strip_prefix("${") else {
+            output.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let end = body
+            .find('}')
+            .ok_or_else(|| format!("unterminated '${{' in {:?}", input))?;
+        let expr = &body[..end];
+        rest = &body[end + 1..];
+
+        // `:-` treats an empty variable as unset, `-` does not
+        let (name, default, allow_empty) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default), false),
+            None => match expr.split_once('-') {
+                Some((name, default)) => (name, Some(default), true),
+                None => (expr, None, true),
+            },
+        };
+
+        if !is_valid_name(name) {
+            return Err(format!("invalid variable name {:?}", name));
+        }
+
+        match (lookup(name).filter(|v| allow_empty || !v.is_empty()), default) {
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => missing.push(name),
+        }
+    }
+    output.push_str(rest);
+
+    if !missing.is_empty() {
+        return Err(format!("environment variable {} is not set", missing.join(", ")));
+    }
+
+    if output == "~" || output.starts_with("~/") || output.starts_with("~\\") {
+        output = expand_tilde(&output).to_string_lossy().into_owned();
+    }
+
+    Ok(output)
+}
+
+/// Whether `path` is a routing rule field that must not be expanded
+fn is_verbatim(path: &str) -> bool {
+    path.strip_prefix("routing.rules[")
+        .and_then(|rest| rest.split_once("]."))
+        .is_some_and(|(_, field)| VERBATIM_RULE_FIELDS.contains(&field))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::home_dir;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "API_KEY" => Some("secret".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate_str() {
+        assert_eq!(interpolate_str("--key=${API_KEY}", &lookup).unwrap(), "--key=secret");
+        assert_eq!(interpolate_str("${MODEL:-qwen}", &lookup).unwrap(), "qwen");
+        assert_eq!(interpolate_str("${EMPTY:-fallback}", &lookup).unwrap(), "fallback");
+        assert_eq!(interpolate_str("${MISSING:-}", &lookup).unwrap(), "");
+        assert_eq!(interpolate_str("${EMPTY-fallback}", &lookup).unwrap(), "");
+        assert_eq!(interpolate_str("${MISSING-fallback}", &lookup).unwrap(), "fallback");
+        assert_eq!(interpolate_str("[${EMPTY}]", &lookup).unwrap(), "[]");
+        assert_eq!(interpolate_str("cost: $$5 or $5", &lookup).unwrap(), "cost: $5 or $5");
+        assert_eq!(interpolate_str("$${API_KEY}", &lookup).unwrap(), "${API_KEY}");
+
+        let home = home_dir().unwrap();
+        assert_eq!(
+            interpolate_str("~/.config/usage.db", &lookup).unwrap(),
+            home.join(".config/usage.db").to_string_lossy()
+        );
+        assert_eq!(interpolate_str("a~b", &lookup).unwrap(), "a~b");
+
+        assert!(interpolate_str("${MISSING}", &lookup).unwrap_err().contains("MISSING"));
+        assert!(interpolate_str("${API_KEY", &lookup).is_err());
+        assert!(interpolate_str("${1BAD}", &lookup).is_err());
+    }
+
+    #[test]
+    fn test_errors_name_field_paths() {
+        let mut table: toml::Table = toml::from_str(r#"
+            [[agents]]
+            id = "qwen"
+            args = ["--token", "${QWEN_TOKEN}"]
+
+            [rate_limiting]
+            usage_db_path = "${DATA_DIR}/usage.db"
+        "#).unwrap();
+
+        let error = interpolate_table_with(&mut table, &lookup).unwrap_err().to_string();
+        assert!(error.contains("agents[0].args[1]: environment variable QWEN_TOKEN is not set"), "{}", error);
+        assert!(error.contains("rate_limiting.usage_db_path"), "{}", error);
+    }
+
+    #[test]
+    fn test_rule_patterns_are_verbatim() {
+        let mut table: toml::Table = toml::from_str(r#"
+            [[routing.rules]]
+            task_type = "${TASK:-code}"
+            keywords = ["foo$", "$${x}"]
+            exclude_keywords = ["${NOT_A_VAR}"]
+            preferred_agents = ["${AGENT:-qwen}"]
+
+            [[routing.rules.context]]
+            pointer = "/branch"
+            glob = "release-$*"
+        "#).unwrap();
+
+        interpolate_table_with(&mut table, &lookup).unwrap();
+        let rule = &table["routing"]["rules"][0];
+        assert_eq!(rule["task_type"].as_str(), Some("code"));
+        assert_eq!(rule["preferred_agents"][0].as_str(), Some("qwen"));
+        assert_eq!(rule["keywords"][0].as_str(), Some("foo$"));
+        assert_eq!(rule["keywords"][1].as_str(), Some("$${x}"));
+        assert_eq!(rule["exclude_keywords"][0].as_str(), Some("${NOT_A_VAR}"));
+        assert_eq!(rule["context"][0]["glob"].as_str(), Some("release-$*"));
+    }
+}
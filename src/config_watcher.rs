@@ -0,0 +1,113 @@
+//! Change detection for config files and the agent library, used to
+//! hot-reload the running server
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often watched files are checked
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls files by content, so atomic saves (write + rename) are picked up
+/// and touching a file without changing it is not
+pub struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<u64>)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mut watcher = Self { files: Vec::new() };
+        watcher.watch(paths);
+        watcher
+    }
+
+    /// Replace the watched set, taking the current contents as the baseline
+    pub fn watch(&mut self, paths: Vec<PathBuf>) {
+        self.files = paths
+            .into_iter()
+            .map(|path| {
+                let fingerprint = Self::fingerprint(&path);
+                (path, fingerprint)
+            })
+            .collect();
+    }
+
+    /// Whether any file changed (or appeared/disappeared) since the last poll
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        for (path, last) in &mut self.files {
+            let current = Self::fingerprint(path);
+            if current != *last {
+                tracing::debug!("Config file {:?} changed", path);
+                *last = current;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Hash of a file's content, or of a directory's file names
+    fn fingerprint(path: &Path) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        if path.is_dir() {
+            let mut names: Vec<_> = fs::read_dir(path)
+                .ok()?
+                .filter_map(|entry| entry.ok().map(|e| e.file_name()))
+                .collect();
+            names.sort();
+            names.hash(&mut hasher);
+        } else {
+            fs::read(path).ok()?.hash(&mut hasher);
+        }
+        Some(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detects_content_changes_only() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("config.toml");
+        fs::write(&path, "a = 1").unwrap();
+
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]);
+        assert!(!watcher.poll());
+
+        // Same length, different content
+        fs::write(&path, "a = 2").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        // Rewriting identical content is not a change
+        fs::write(&path, "a = 2").unwrap();
+        assert!(!watcher.poll());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll());
+    }
+
+    #[test]
+    fn test_detects_added_files_in_directories() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.md"), "a").unwrap();
+
+        let mut watcher = ConfigWatcher::new(vec![temp.path().to_path_buf()]);
+        assert!(!watcher.poll());
+
+        fs::write(temp.path().join("b.md"), "b").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        // File contents are watched per file, not through the directory
+        fs::write(temp.path().join("a.md"), "changed").unwrap();
+        assert!(!watcher.poll());
+    }
+}
@@ -0,0 +1,492 @@
+//! Daemon mode: one orchestrator in the background, shared by every client
+//! that connects to its Unix socket (directly or through `connect`)
+
+use crate::config::home_dir;
+use crate::mcp::Orchestrator;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Set in the detached child, which then runs the daemon in the foreground
+pub const CHILD_ENV: &str = "BL1NK_DAEMON_CHILD";
+
+/// How long `serve --daemon` and `stop` wait for the daemon
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+const WAIT_STEP: Duration = Duration::from_millis(100);
+
+/// Files of a daemon, all next to its socket
+#[derive(Debug, Clone)]
+pub struct DaemonPaths {
+    pub socket: PathBuf,
+    pub pidfile: PathBuf,
+    /// stderr of the detached process
+    pub log: PathBuf,
+}
+
+impl DaemonPaths {
+    /// `socket`, or `~/.config/bl1nk-agents-manager/daemon.sock` (without a
+    /// home directory, `bl1nk-agents-manager-<uid>/` in the temp directory)
+    pub fn new(socket: Option<PathBuf>) -> Self {
+        let socket = socket.unwrap_or_else(|| {
+            home_dir()
+                .map(|home| home.join(".config/bl1nk-agents-manager"))
+                .unwrap_or_else(|| {
+                    // SAFETY: getuid takes no arguments, touches no memory and cannot fail
+                    let uid = unsafe { libc::getuid() };
+                    std::env::temp_dir().join(format!("bl1nk-agents-manager-{}", uid))
+                })
+                .join("daemon.sock")
+        });
+
+        Self {
+            pidfile: socket.with_extension("pid"),
+            log: socket.with_extension("log"),
+            socket,
+        }
+    }
+
+    /// PID of the running daemon. The daemon holds a lock on its pidfile for
+    /// as long as it runs, so a PID left behind (and maybe reused by an
+    /// unrelated process) is never reported.
+    pub fn running_pid(&self) -> Option<i32> {
+        let mut file = File::open(&self.pidfile).ok()?;
+        if !matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)) {
+            return None;
+        }
+        let mut content = String::new();
+        file.read_to_string(&mut content).ok()?;
+        content.trim().parse().ok()
+    }
+
+    /// Take the daemon's exclusive pidfile lock; `None` while another
+    /// process holds it
+    fn lock_pidfile(&self) -> Result<Option<File>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.pidfile)
+            .with_context(|| format!("Cannot open pidfile {}", self.pidfile.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(file)),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Cannot lock pidfile {}", self.pidfile.display()))
+            }
+        }
+    }
+
+    /// Directory of the daemon files, private to the user when created here
+    fn create_dir(&self) -> Result<()> {
+        if let Some(dir) = self.socket.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Callers hold the pidfile lock, so no running daemon loses its socket
+    fn remove_files(&self) {
+        let _ = fs::remove_file(&self.socket);
+        let _ = fs::remove_file(&self.pidfile);
+    }
+
+    fn already_running(&self) -> anyhow::Error {
+        match self.running_pid() {
+            Some(pid) => anyhow::anyhow!("Daemon already running (pid {}) on {}", pid, self.socket.display()),
+            None => anyhow::anyhow!("Daemon already running on {}", self.socket.display()),
+        }
+    }
+}
+
+/// `serve --daemon`: start this command again in its own session, detached
+/// from the terminal, and wait until its socket accepts connections
+pub async fn spawn_detached(paths: &DaemonPaths) -> Result<()> {
+    if paths.running_pid().is_some() {
+        return Err(paths.already_running());
+    }
+    paths.create_dir()?;
+
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&paths.log)
+        .with_context(|| format!("Cannot open daemon log {}", paths.log.display()))?;
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log);
+    // No controlling terminal, so closing the shell does not stop the daemon
+    // SAFETY: the closure runs in the forked child before exec, where only
+    // async-signal-safe calls are allowed. It calls setsid, which is one, and
+    // reads errno; it allocates nothing and takes no locks.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().context("Failed to start the daemon process")?;
+
+    // Another daemon started at the same time may own the socket; wait for ours
+    let mut waited = Duration::ZERO;
+    while waited < WAIT_TIMEOUT {
+        if paths.running_pid() == Some(child.id() as i32) && UnixStream::connect(&paths.socket).await.is_ok() {
+            println!("✅ Daemon started (pid {}) on {}", child.id(), paths.socket.display());
+            println!("   Log: {}", paths.log.display());
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            bail!("Daemon exited during startup ({}), see {}", status, paths.log.display());
+        }
+        tokio::time::sleep(WAIT_STEP).await;
+        waited += WAIT_STEP;
+    }
+
+    bail!(
+        "Daemon did not open {} within {}s, see {}",
+        paths.socket.display(),
+        WAIT_TIMEOUT.as_secs(),
+        paths.log.display()
+    )
+}
+
+/// Run the daemon in the foreground until SIGTERM or Ctrl+C. Each connection
+/// is its own MCP session; agents, tasks and rate limits are shared.
+pub async fn run(orchestrator: Arc<Orchestrator>, paths: &DaemonPaths) -> Result<()> {
+    paths.create_dir()?;
+    // Held until this function returns; a second daemon stops here
+    let Some(mut pidfile) = paths.lock_pidfile()? else {
+        return Err(paths.already_running());
+    };
+    pidfile.set_len(0)?;
+    pidfile.write_all(format!("{}\n", std::process::id()).as_bytes())
+        .with_context(|| format!("Cannot write pidfile {}", paths.pidfile.display()))?;
+
+    // A socket left behind by a daemon that did not shut down cleanly
+    let _ = fs::remove_file(&paths.socket);
+    let listener = UnixListener::bind(&paths.socket)
+        .with_context(|| format!("Cannot listen on {}", paths.socket.display()))?;
+    tracing::info!("🧷 Daemon (pid {}) listening on {}", std::process::id(), paths.socket.display());
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let result = tokio::select! {
+        result = accept_loop(orchestrator, listener) => result,
+        _ = terminate.recv() => Ok(()),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+
+    tracing::info!("👋 Daemon shutting down");
+    paths.remove_files();
+    drop(pidfile);
+    result
+}
+
+async fn accept_loop(orchestrator: Arc<Orchestrator>, listener: UnixListener) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let orchestrator = orchestrator.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(&orchestrator, stream).await {
+                tracing::warn!("⚠️  Daemon session ended with an error: {:#}", e);
+            }
+        });
+    }
+}
+
+async fn serve_connection(orchestrator: &Orchestrator, stream: UnixStream) -> Result<()> {
+    let (reader, writer) = stream.into_split();
+    tracing::debug!("🔌 Client connected");
+
+    orchestrator.serve_stream(reader, writer).await?;
+
+    tracing::debug!("🔌 Client disconnected");
+    Ok(())
+}
+
+/// `connect`: forward stdio to the daemon, for MCP clients that spawn a command
+pub async fn connect(paths: &DaemonPaths) -> Result<()> {
+    let stream = UnixStream::connect(&paths.socket).await.with_context(|| format!(
+        "Cannot connect to {}; start the daemon with `serve --daemon`",
+        paths.socket.display()
+    ))?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    // stdin is read on a plain thread: a read blocked there does not keep the
+    // runtime from shutting down once the daemon hangs up
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buffer = vec![0; 8192];
+        loop {
+            match stdin.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.blocking_send(buffer[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let upload = async {
+        while let Some(chunk) = rx.recv().await {
+            writer.write_all(&chunk).await?;
+        }
+        writer.shutdown().await
+    };
+    let mut stdout = tokio::io::stdout();
+    let download = tokio::io::copy(&mut reader, &mut stdout);
+    tokio::pin!(download);
+
+    tokio::select! {
+        result = &mut download => {
+            result?;
+        }
+        result = upload => {
+            // Client is done; pass on what the daemon still sends
+            result?;
+            download.await?;
+        }
+    }
+    Ok(())
+}
+
+/// `status`: whether the daemon runs, and its task counts. Returns false
+/// when it is not running.
+pub async fn status(paths: &DaemonPaths) -> Result<bool> {
+    let Some(pid) = paths.running_pid() else {
+        println!("⚪ Daemon is not running ({})", paths.socket.display());
+        return Ok(false);
+    };
+    println!("🟢 Daemon running (pid {}) on {}", pid, paths.socket.display());
+
+    match tokio::time::timeout(WAIT_TIMEOUT, query_agent_status(&paths.socket)).await {
+        Ok(Ok(status)) => {
+            println!(
+                "   Tasks: {} active ({} running, {} queued), at most {} at once",
+                status["active_tasks"],
+                status["running_tasks"],
+                status["queued_tasks"],
+                status["max_concurrent_tasks"]
+            );
+            let agents = status["available_agents"].as_array().map(Vec::len).unwrap_or_default();
+            println!("   Agents: {}", agents);
+        }
+        Ok(Err(e)) => println!("⚠️  Daemon did not answer: {:#}", e),
+        Err(_) => println!("⚠️  Daemon did not answer within {}s", WAIT_TIMEOUT.as_secs()),
+    }
+    Ok(true)
+}
+
+/// Call `agent_status` over a fresh session
+async fn query_agent_status(socket: &Path) -> Result<Value> {
+    let stream = UnixStream::connect(socket).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let messages = [
+        json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "bl1nk-status", "version": env!("CARGO_PKG_VERSION") },
+            },
+        }),
+        json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": { "name": "agent_status", "arguments": {} },
+        }),
+    ];
+    for message in messages {
+        writer.write_all(format!("{}\n", message).as_bytes()).await?;
+    }
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let response: Value = serde_json::from_str(&line)?;
+        if response["id"] != 2 {
+            continue;
+        }
+        if let Some(error) = response.get("error") {
+            bail!("agent_status failed: {}", error);
+        }
+        let text = response["result"]["content"][0]["text"]
+            .as_str()
+            .context("Unexpected agent_status response")?;
+        return Ok(serde_json::from_str(text)?);
+    }
+
+    bail!("Daemon closed the connection")
+}
+
+/// `stop`: SIGTERM the daemon and wait for it to exit. Returns false when
+/// it was not running.
+pub async fn stop(paths: &DaemonPaths) -> Result<bool> {
+    // Only a PID whose pidfile is still locked belongs to the daemon
+    let Some(pid) = paths.running_pid() else {
+        println!("⚪ Daemon is not running ({})", paths.socket.display());
+        if let Some(_lock) = paths.lock_pidfile()? {
+            paths.remove_files();
+        }
+        return Ok(false);
+    };
+
+    // SAFETY: kill only takes plain integers; `pid` is the daemon, since it
+    // still holds the pidfile lock
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("Cannot signal pid {}", pid));
+    }
+
+    let mut waited = Duration::ZERO;
+    while waited < WAIT_TIMEOUT {
+        if paths.running_pid() != Some(pid) {
+            println!("🛑 Daemon stopped (pid {})", pid);
+            return Ok(true);
+        }
+        tokio::time::sleep(WAIT_STEP).await;
+        waited += WAIT_STEP;
+    }
+
+    bail!("Daemon (pid {}) did not exit within {}s", pid, WAIT_TIMEOUT.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AgentConfig, Config};
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    fn test_config(temp: &TempDir) -> Config {
+        let mut config: Config = toml::from_str(&format!(r#"
+            agents = []
+
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [routing]
+            rules = []
+
+            [rate_limiting]
+            usage_db_path = "{}"
+
+            [logging]
+
+            [agent_library]
+            enabled = false
+        "#, temp.path().join("usage.db").display())).unwrap();
+        config.agents = vec![AgentConfig {
+            id: "shared-agent".to_string(),
+            name: "Shared".to_string(),
+            agent_type: "cli".to_string(),
+            command: Some("true".to_string()),
+            enabled: true,
+            ..Default::default()
+        }];
+        config
+    }
+
+    #[test]
+    fn test_paths_and_stale_pidfile() {
+        let temp = TempDir::new().unwrap();
+        let paths = DaemonPaths::new(Some(temp.path().join("daemon.sock")));
+        assert_eq!(paths.pidfile, temp.path().join("daemon.pid"));
+        assert_eq!(paths.log, temp.path().join("daemon.log"));
+
+        assert_eq!(paths.running_pid(), None);
+        fs::write(&paths.pidfile, "not a pid").unwrap();
+        assert_eq!(paths.running_pid(), None);
+
+        // A live PID is not enough: it may have been reused by another process
+        fs::write(&paths.pidfile, format!("{}\n", std::process::id())).unwrap();
+        assert_eq!(paths.running_pid(), None);
+
+        let lock = paths.lock_pidfile().unwrap().unwrap();
+        assert_eq!(paths.running_pid(), Some(std::process::id() as i32));
+        assert!(paths.lock_pidfile().unwrap().is_none());
+        drop(lock);
+        assert_eq!(paths.running_pid(), None);
+    }
+
+    #[tokio::test]
+    async fn test_socket_sessions_share_orchestrator() {
+        let temp = TempDir::new().unwrap();
+        let paths = DaemonPaths::new(Some(temp.path().join("daemon.sock")));
+        let orchestrator = Arc::new(Orchestrator::new(test_config(&temp)).await.unwrap());
+
+        let daemon = tokio::spawn({
+            let paths = paths.clone();
+            async move { run(orchestrator, &paths).await }
+        });
+        for _ in 0..50 {
+            if UnixStream::connect(&paths.socket).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(WAIT_STEP).await;
+        }
+        assert_eq!(paths.running_pid(), Some(std::process::id() as i32));
+
+        // A second daemon gives up without touching the first one's socket
+        let second = Arc::new(Orchestrator::new(test_config(&temp)).await.unwrap());
+        let error = run(second, &paths).await.unwrap_err().to_string();
+        assert!(error.contains("already running"), "{}", error);
+
+        // Line-delimited session
+        let status = query_agent_status(&paths.socket).await.unwrap();
+        assert_eq!(status["available_agents"], json!(["shared-agent"]));
+
+        // Content-Length session, answered in the same framing
+        let body = json!({
+            "jsonrpc": "2.0", "id": 7, "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "1.0.0" },
+            },
+        }).to_string();
+        let mut stream = UnixStream::connect(&paths.socket).await.unwrap();
+        stream.write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes()).await.unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut header = String::new();
+        reader.read_line(&mut header).await.unwrap();
+        let length: usize = header.trim().strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        let mut blank = String::new();
+        reader.read_line(&mut blank).await.unwrap();
+        let mut response = vec![0; length];
+        reader.read_exact(&mut response).await.unwrap();
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response["id"], 7);
+        assert!(response["result"]["serverInfo"].is_object());
+
+        daemon.abort();
+    }
+}
@@ -0,0 +1,287 @@
+//! `doctor` checks: setups that load fine but fail or misroute at runtime
+
+use crate::config::{expand_tilde, AgentConfig, Config, RoutingRule};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(message: String) -> Self {
+        Self { severity: Severity::Error, message }
+    }
+
+    fn warning(message: String) -> Self {
+        Self { severity: Severity::Warning, message }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.severity {
+            Severity::Error => "❌",
+            Severity::Warning => "⚠️ ",
+        };
+        write!(f, "{} {}", icon, self.message)
+    }
+}
+
+/// Run every check against a loaded (not necessarily valid) config
+pub fn check(config: &Config) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = config
+        .validation_errors()
+        .into_iter()
+        .map(Finding::error)
+        .collect();
+
+    findings.extend(check_commands(&config.agents));
+    findings.extend(check_preferred_agents(config));
+    findings.extend(check_shadowed_rules(config));
+    findings
+}
+
+/// Every CLI agent's command must resolve to an executable
+fn check_commands(agents: &[AgentConfig]) -> Vec<Finding> {
+    agents
+        .iter()
+        .filter(|agent| agent.agent_type == "cli")
+        .filter_map(|agent| {
+            let Some(command) = &agent.command else {
+                return Some(Finding::error(format!("Agent '{}' has no command", agent.id)));
+            };
+
+            // The agent's own PATH is used for lookup when it sets one
+            let path_var = agent
+                .env
+                .get("PATH")
+                .map(OsString::from)
+                .or_else(|| std::env::var_os("PATH"));
+
+            match resolve_command(command, path_var) {
+                Some(_) => None,
+                None => Some(Finding::error(format!(
+                    "Command of agent '{}' not found on PATH: {}",
+                    agent.id, command
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Find the executable `command` would run
+pub fn resolve_command(command: &str, path_var: Option<OsString>) -> Option<PathBuf> {
+    let command_path = expand_tilde(command);
+    if command_path.components().count() > 1 {
+        return is_executable(&command_path).then_some(command_path);
+    }
+
+    std::env::split_paths(&path_var?)
+        .flat_map(|dir| executable_candidates(&dir, command))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn executable_candidates(dir: &Path, command: &str) -> Vec<PathBuf> {
+    vec![dir.join(command)]
+}
+
+#[cfg(not(unix))]
+fn executable_candidates(dir: &Path, command: &str) -> Vec<PathBuf> {
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string());
+    std::iter::once(dir.join(command))
+        .chain(extensions.split(';').map(|ext| dir.join(format!("{}{}", command, ext))))
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn check_preferred_agents(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (index, rule) in config.routing.rules.iter().enumerate() {
+        for agent_id in &rule.preferred_agents {
+            if !config.agents.iter().any(|agent| &agent.id == agent_id) {
+                findings.push(Finding::warning(format!(
+                    "{} prefers unknown agent '{}'",
+                    describe_rule(index, rule),
+                    agent_id
+                )));
+            }
+        }
+    }
+
+    findings
+}
+
+/// A rule without keyword conditions matches every prompt of its task type,
+/// so any keyword rule it is tried before only ever runs as failover
+fn check_shadowed_rules(config: &Config) -> Vec<Finding> {
+    let rules: Vec<(usize, &RoutingRule)> = config
+        .routing
+        .rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.enabled)
+        .collect();
+    let rank = |rule: &RoutingRule| {
+        (rule.tier.clone().unwrap_or_else(|| config.routing.tier.clone()), rule.priority)
+    };
+
+    let mut findings = Vec::new();
+    for &(general_index, general) in rules.iter().filter(|(_, rule)| !rule.has_conditions()) {
+        for &(specific_index, specific) in &rules {
+            if !specific.has_conditions() || specific.task_type != general.task_type {
+                continue;
+            }
+
+            // Rules are sorted by (tier, priority); ties keep config order
+            let tried_first = match rank(general).cmp(&rank(specific)) {
+                std::cmp::Ordering::Greater => true,
+                std::cmp::Ordering::Equal => general_index < specific_index,
+                std::cmp::Ordering::Less => false,
+            };
+
+            if tried_first {
+                findings.push(Finding::warning(format!(
+                    "{} has no keywords and is tried before {} (keywords {:?}), which then only runs as failover",
+                    describe_rule(general_index, general),
+                    describe_rule(specific_index, specific),
+                    specific.patterns().collect::<Vec<_>>()
+                )));
+            }
+        }
+    }
+
+    findings
+}
+
+fn describe_rule(index: usize, rule: &RoutingRule) -> String {
+    format!(
+        "Rule #{} (task_type '{}', priority {})",
+        index + 1,
+        rule.task_type,
+        rule.priority
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RoutingTier;
+
+    fn config_with(agents: Vec<AgentConfig>, rules: Vec<RoutingRule>) -> Config {
+        let mut config: Config = toml::from_str(r#"
+            agents = []
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+            [routing]
+            rules = []
+            [rate_limiting]
+            usage_db_path = "/tmp/usage.db"
+            [logging]
+        "#).unwrap();
+        config.agents = agents;
+        config.routing.rules = rules;
+        config
+    }
+
+    fn cli_agent(id: &str, command: &str) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: "cli".to_string(),
+            command: Some(command.to_string()),
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn rule(task_type: &str, keywords: &[&str], priority: u16, agents: &[&str]) -> RoutingRule {
+        RoutingRule {
+            task_type: task_type.to_string(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            preferred_agents: agents.iter().map(|a| a.to_string()).collect(),
+            priority,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commands_resolve_on_path() {
+        let config = config_with(
+            vec![cli_agent("shell", "sh"), cli_agent("ghost", "definitely-not-installed-xyz")],
+            vec![],
+        );
+
+        let findings = check(&config);
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[0].message.contains("'ghost'"));
+    }
+
+    #[test]
+    fn test_unknown_preferred_agent() {
+        let config = config_with(
+            vec![cli_agent("a", "sh")],
+            vec![rule("code", &["rust"], 100, &["a", "missing"])],
+        );
+
+        let findings = check_preferred_agents(&config);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("'missing'"));
+    }
+
+    #[test]
+    fn test_keywordless_rule_shadowing() {
+        let mut admin_catch_all = rule("code", &[], 10, &["a"]);
+        admin_catch_all.tier = Some(RoutingTier::Admin);
+
+        let config = config_with(
+            vec![cli_agent("a", "sh")],
+            vec![
+                rule("code", &["rust"], 200, &["a"]),       // #1 beats the catch-all below
+                rule("code", &[], 100, &["a"]),             // #2
+                rule("code", &["python"], 100, &["a"]),     // #3 tie, after #2: shadowed
+                rule("docs", &["readme"], 500, &["a"]),     // #4 other task type
+                admin_catch_all,                            // #5 admin tier beats everything
+            ],
+        );
+
+        let shadowed: Vec<String> = check_shadowed_rules(&config)
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect();
+
+        assert_eq!(shadowed.len(), 3, "{:#?}", shadowed);
+        assert!(shadowed[0].starts_with("Rule #2") && shadowed[0].contains("before Rule #3"));
+        assert!(shadowed[1].starts_with("Rule #5") && shadowed[1].contains("before Rule #1"));
+        assert!(shadowed[2].starts_with("Rule #5") && shadowed[2].contains("before Rule #3"));
+    }
+}
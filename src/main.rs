@@ -1,61 +1,182 @@
 mod config;
+mod config_interpolation;
+mod config_watcher;
+#[cfg(unix)]
+mod daemon;
+mod doctor;
 mod mcp;
 mod agents;
 mod rate_limit;
+mod usage_store;
 
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use tracing_subscriber::EnvFilter;
 
 /// BL1NK Agents Manager - Intelligent MCP/ACP Orchestrator
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Path to configuration file
-    #[arg(short, long, value_name = "FILE")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to configuration file (after the subcommand, if any)
+    #[arg(short, long, value_name = "FILE", global = true)]
     config: Option<PathBuf>,
 
-    /// Server host address
-    #[arg(long, default_value = "127.0.0.1")]
-    host: String,
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(short, long, global = true)]
+    log_level: Option<String>,
 
-    /// Server port
-    #[arg(short, long, default_value_t = 3000)]
-    port: u16,
+    /// Server options when no subcommand is given (same as `serve`)
+    #[command(flatten)]
+    serve: ServeArgs,
+}
 
-    /// Log level (trace, debug, info, warn, error)
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the MCP server (stdio by default)
+    Serve(ServeArgs),
+    /// Check a config file and report every problem at once
+    Validate {
+        /// Config file to check
+        file: PathBuf,
+    },
+    /// Check commands, routing rules and agent references for common mistakes
+    Doctor,
+    /// Show which agent a task would be routed to and why, without running it
+    Route {
+        /// Task type, as passed to delegate_task
+        #[arg(long)]
+        task_type: String,
+
+        /// Prompt the task would be delegated with
+        #[arg(long)]
+        prompt: String,
+
+        /// Task context as JSON, for rules with context conditions
+        #[arg(long)]
+        context: Option<String>,
+
+        /// Print the explanation as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Forward stdio to the running daemon, for MCP clients that spawn a command
+    Connect(SocketArgs),
+    /// Show whether the daemon is running and how busy it is
+    Status(SocketArgs),
+    /// Stop the running daemon
+    Stop(SocketArgs),
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct SocketArgs {
+    /// Daemon socket (default: ~/.config/bl1nk-agents-manager/daemon.sock);
+    /// the pidfile and log sit next to it
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct ServeArgs {
+    /// How MCP clients connect
+    #[arg(long, value_enum, default_value_t = Transport::Stdio)]
+    transport: Transport,
 
-    /// Run in daemon mode (background)
+    /// HTTP listen address (default: `[server] host`)
+    #[arg(long)]
+    host: Option<String>,
+
+    /// HTTP listen port (default: `[server] port`)
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Detach and serve every client through the daemon socket (see `connect`)
     #[arg(short, long)]
     daemon: bool,
+
+    #[command(flatten)]
+    socket: SocketArgs,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    /// One client, which spawns this process
+    Stdio,
+    /// MCP streamable HTTP with SSE; many clients share one orchestrator
+    Http,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     // Parse CLI arguments
     let args = Args::parse();
+    let command = args.command.unwrap_or(Command::Serve(args.serve));
+
+    // Checks print their own report, so only warnings are logged by default
+    let default_level = match command {
+        Command::Serve(_) => "info",
+        _ => "warn",
+    };
+    let log_level = args.log_level.as_deref().unwrap_or(default_level);
 
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
             EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new(&args.log_level))
+                .unwrap_or_else(|_| EnvFilter::new(log_level))
         )
         .with_writer(std::io::stderr) // Force logs to stderr
+        .with_ansi(std::io::stderr().is_terminal()) // Plain text in the daemon log
         .with_target(true)
         .with_thread_ids(true)
         .with_file(true)
         .with_line_number(true)
         .init();
 
+    match command {
+        Command::Serve(serve_args) => {
+            serve(args.config, serve_args).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Validate { file } => Ok(validate(&file)),
+        Command::Doctor => doctor(args.config.as_deref()),
+        Command::Route { task_type, prompt, context, json } => {
+            route(args.config.as_deref(), &task_type, &prompt, context.as_deref(), json).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        #[cfg(unix)]
+        Command::Connect(socket) => {
+            daemon::connect(&daemon::DaemonPaths::new(socket.socket)).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        #[cfg(unix)]
+        Command::Status(socket) => {
+            let running = daemon::status(&daemon::DaemonPaths::new(socket.socket)).await?;
+            Ok(if running { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+        #[cfg(unix)]
+        Command::Stop(socket) => {
+            daemon::stop(&daemon::DaemonPaths::new(socket.socket)).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        #[cfg(not(unix))]
+        Command::Connect(_) | Command::Status(_) | Command::Stop(_) => {
+            anyhow::bail!("Daemon mode needs Unix domain sockets")
+        }
+    }
+}
+
+async fn serve(config_path: Option<PathBuf>, serve: ServeArgs) -> Result<()> {
     tracing::info!("🚀 Starting BL1NK Agents Manager");
     tracing::info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
     // Load configuration
-    let config = if let Some(config_path) = args.config {
+    let config = if let Some(config_path) = &config_path {
         tracing::info!("Loading config from: {:?}", config_path);
         config::Config::load(config_path)?
     } else {
@@ -69,14 +190,240 @@ async fn main() -> Result<()> {
     // Log routing tier
     tracing::info!("📊 Routing tier: {:?}", config.routing.tier);
 
+    let host = serve.host.unwrap_or_else(|| config.server.host.clone());
+    let port = serve.port.unwrap_or(config.server.port);
+
+    // The detached copy serves; this process has only checked the config
+    #[cfg(unix)]
+    if serve.daemon && std::env::var_os(daemon::CHILD_ENV).is_none() {
+        return daemon::spawn_detached(&daemon::DaemonPaths::new(serve.socket.socket)).await;
+    }
+    #[cfg(not(unix))]
+    if serve.daemon {
+        anyhow::bail!("--daemon needs Unix domain sockets");
+    }
+
     // Initialize the orchestrator
     let orchestrator = mcp::Orchestrator::new(config).await?;
 
+    // Pick up config edits without restarting the server
+    orchestrator.spawn_config_watcher(move || match &config_path {
+        Some(config_path) => config::Config::load(config_path),
+        None => config::Config::load_default(),
+    }).await;
+
     // Run the MCP server
-    tracing::info!("🎧 Starting MCP server on stdio");
-    tracing::info!("Host: {} | Port: {}", args.host, args.port);
-    
-    orchestrator.run_stdio().await?;
+    let http = match serve.transport {
+        Transport::Http => Some(start_http(&orchestrator, &host, port).await?),
+        Transport::Stdio => None,
+    };
+
+    // Daemon clients share this orchestrator with HTTP clients, if any
+    #[cfg(unix)]
+    if serve.daemon {
+        let paths = daemon::DaemonPaths::new(serve.socket.socket);
+        return daemon::run(std::sync::Arc::new(orchestrator), &paths).await;
+    }
+
+    match http {
+        None => {
+            tracing::info!("🎧 Starting MCP server on stdio");
+            orchestrator.run_stdio().await?;
+        }
+        Some(server) => {
+            tokio::select! {
+                result = server => result?,
+                _ = tokio::signal::ctrl_c() => tracing::info!("👋 Shutting down"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Serve streamable HTTP in the background on `host:port`
+async fn start_http(
+    orchestrator: &mcp::Orchestrator,
+    host: &str,
+    port: u16,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Cannot resolve {}:{}", host, port))?
+        .next()
+        .with_context(|| format!("No address for {}:{}", host, port))?;
+
+    let (bound, server) = orchestrator.start_http(addr).await
+        .with_context(|| format!("Cannot listen on {}", addr))?;
+    tracing::info!("🌐 MCP streamable HTTP server listening on http://{}/", bound);
+    Ok(server)
+}
+
+/// `validate <file>`: print every problem instead of stopping at the first
+fn validate(file: &Path) -> ExitCode {
+    let config = match config::Config::load_unvalidated(file) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("❌ {}: {:#}", file.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let errors = config.validation_errors();
+    if errors.is_empty() {
+        println!(
+            "✅ {} is valid ({} agents, {} routing rules)",
+            file.display(),
+            config.agents.len(),
+            config.routing.rules.len()
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    println!("❌ {} has {} problem(s):", file.display(), errors.len());
+    for error in errors {
+        println!("  - {}", error);
+    }
+    ExitCode::FAILURE
+}
+
+/// `doctor`: runtime checks on top of validation
+fn doctor(config_path: Option<&Path>) -> Result<ExitCode> {
+    let config = match config_path {
+        Some(path) => config::Config::load_unvalidated(path)?,
+        None => config::Config::load_default_unvalidated()?,
+    };
+
+    let findings = doctor::check(&config);
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == doctor::Severity::Error)
+        .count();
+
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    if findings.is_empty() {
+        println!("✅ No problems found ({} agents, {} routing rules)", config.agents.len(), config.routing.rules.len());
+    } else {
+        println!("\n{} error(s), {} warning(s)", errors, findings.len() - errors);
+    }
+
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// `route`: routing dry run against the current config and usage
+async fn route(
+    config_path: Option<&Path>,
+    task_type: &str,
+    prompt: &str,
+    context: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let context: Option<serde_json::Value> = context
+        .map(serde_json::from_str)
+        .transpose()
+        .context("--context is not valid JSON")?;
+
+    let config = match config_path {
+        Some(path) => config::Config::load(path)?,
+        None => config::Config::load_default()?,
+    };
+
+    let orchestrator = mcp::Orchestrator::new(config).await?;
+    let explanation = orchestrator.explain_route(task_type, prompt, context.as_ref()).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&explanation)?);
+        return Ok(());
+    }
+
+    println!(
+        "Routing task_type '{}' ({:?} mode, {:?} strategy)\n",
+        explanation.task_type, explanation.mode, explanation.strategy
+    );
+
+    for rule in &explanation.rules {
+        let header = format!("Rule #{} [tier {:?}, priority {}]", rule.rule, rule.tier, rule.priority);
+        if !rule.enabled {
+            println!("⏸️  {}: disabled", header);
+        } else if !rule.task_type_matched {
+            println!("❌ {}: task_type '{}' does not match", header, rule.task_type);
+        } else if let Some(excluded) = &rule.excluded_by {
+            println!("❌ {}: excluded by keyword {:?}", header, excluded);
+        } else if !rule.keywords_matched {
+            println!("❌ {}: no keyword found in the prompt {:?} ({:?})", header, rule.keywords, rule.match_mode);
+        } else if !rule.missing_all_of.is_empty() {
+            println!("❌ {}: all_of keywords missing {:?}", header, rule.missing_all_of);
+        } else if !rule.any_of_matched {
+            println!("❌ {}: no any_of keyword found in the prompt", header);
+        } else if let Some(condition) = &rule.failed_context {
+            println!("❌ {}: context condition {} does not hold", header, condition);
+        } else {
+            let matched_by = match &rule.matched_keyword {
+                Some(keyword) => format!("keyword {:?}", keyword),
+                None if !rule.context.is_empty() => format!("context {}", rule.context.join(", ")),
+                None => "no keywords".to_string(),
+            };
+            println!(
+                "✅ {}: matched ({}), tried #{}",
+                header,
+                matched_by,
+                rule.order.unwrap_or_default()
+            );
+        }
+
+        for agent in &rule.preferred_agents {
+            if !agent.available {
+                println!("     - {}: not registered", agent.agent_id);
+                continue;
+            }
+            println!(
+                "     - {}: {}, {}/day and {}/min left, {} active task(s)",
+                agent.agent_id,
+                if agent.has_quota { "has quota" } else { "out of quota" },
+                agent.remaining_per_day.unwrap_or_default(),
+                agent.remaining_per_minute.unwrap_or_default(),
+                agent.active_tasks
+            );
+        }
+    }
+
+    if explanation.rules.is_empty() {
+        println!("(no routing rules)");
+    }
+
+    if !explanation.scores.is_empty() {
+        println!("\nScores (best {} of {}):", explanation.scores.len().min(10), explanation.scores.len());
+        for score in explanation.scores.iter().take(10) {
+            println!(
+                "  {:>7.2}  {}: {} keyword hit(s), {} shared capability(ies), rule rank {:.2}, priority {}, semantic {:.2}, {} active{}",
+                score.score,
+                score.agent_id,
+                score.keyword_hits,
+                score.capability_overlap,
+                score.rule_rank,
+                score.agent_priority,
+                score.semantic,
+                score.active_tasks,
+                if score.has_quota { "" } else { ", out of quota" }
+            );
+        }
+    }
+
+    if !explanation.semantic_matches.is_empty() {
+        println!("\nBM25 matches:");
+        for semantic in &explanation.semantic_matches {
+            println!("  {:>7.2}  {}", semantic.score, semantic.agent_id);
+        }
+    }
+
+    println!("\nCandidates in order: {}", explanation.ranked_agents.join(", "));
+    match &explanation.selected_agent {
+        Some(agent_id) => println!("➡️  Selected '{}': {}", agent_id, explanation.reason),
+        None => println!("❌ No agent selected: {}", explanation.reason),
+    }
 
     Ok(())
 }
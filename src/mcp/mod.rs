@@ -1,18 +1,50 @@
-use crate::config::Config;
-use crate::agents::{AgentRegistry, AgentExecutor};
+// Wire types for ACP clients; the server itself only uses the error codes so far
+#[allow(dead_code)]
+pub mod protocol;
+pub mod prompts;
+pub mod resources;
+pub mod transport;
+
+use crate::config::{AgentConfig, CommandsConfig, Config};
+use crate::config_watcher::{ConfigWatcher, POLL_INTERVAL};
+use crate::agents::{AdmissionController, AgentRegistry, AgentExecutor, register::TaskInfo};
+use crate::agents::router::{AgentRule, RouteExplanation};
 use crate::rate_limit::RateLimitTracker;
 use anyhow::Result;
-use pmcp::{ServerBuilder, TypedTool, RequestHandlerExtra, Error as McpError};
+use pmcp::{ServerBuilder, ToolHandler, TypedTool, RequestHandlerExtra};
+use pmcp::types::{ResourceCapabilities, ServerCapabilities};
+use pmcp::server::http_middleware::ServerHttpMiddlewareChain;
+use pmcp::server::streamable_http_server::{StreamableHttpServer, StreamableHttpServerConfig};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
+use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{watch, RwLock};
+use transport::SessionControl;
 
 pub struct Orchestrator {
-    config: Config,
+    config: Arc<RwLock<Config>>,
     agent_registry: Arc<RwLock<AgentRegistry>>,
+    /// Shared with the executor; held here so tests can inspect usage
+    #[cfg_attr(not(test), allow(dead_code))]
     rate_limiter: Arc<RwLock<RateLimitTracker>>,
+    admission: Arc<AdmissionController>,
     executor: Arc<AgentExecutor>,
+    /// Fires when a reload changes the agent library resources
+    resources_changed: watch::Sender<()>,
+    /// Where command prompts are read from, for each new session
+    commands: CommandsConfig,
+}
+
+/// Transport an MCP server is built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionKind {
+    /// One client per server, told when resources change
+    Stream,
+    /// Every HTTP client shares one server, which handles a request at a time
+    Http,
 }
 
 /// Arguments for delegating a task to a sub-agent
@@ -28,12 +60,18 @@ pub struct DelegateTaskArgs {
     #[schemars(description = "Optional agent ID to use (auto-selects if not provided)")]
     pub agent_id: Option<String>,
 
-    #[schemars(description = "Run as background task")]
+    #[schemars(description = "Run as background task (always on over HTTP)")]
     #[serde(default)]
     pub background: bool,
 
     #[schemars(description = "Additional context as JSON")]
     pub context: Option<serde_json::Value>,
+
+    #[schemars(description = "Optional execution timeout in seconds (overrides the agent's timeout_secs)")]
+    pub timeout_secs: Option<u64>,
+
+    #[schemars(description = "Optional session ID: follow-up tasks of the same type stay with the agent that ran the previous one")]
+    pub session_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -42,6 +80,15 @@ pub struct DelegateTaskOutput {
     pub agent_id: String,
     pub status: String,
     pub result: Option<String>,
+    /// Candidates passed over before `agent_id` was chosen
+    pub skipped_agents: Vec<SkippedAgent>,
+}
+
+/// A candidate agent that was not used for a task
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SkippedAgent {
+    pub agent_id: String,
+    pub reason: String,
 }
 
 /// Arguments for querying agent status
@@ -55,8 +102,157 @@ pub struct AgentStatusArgs {
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct AgentStatusOutput {
     pub active_tasks: usize,
+    /// Tasks waiting for an execution slot
+    pub queued_tasks: usize,
+    /// Tasks holding an execution slot
+    pub running_tasks: usize,
+    pub max_concurrent_tasks: usize,
     pub available_agents: Vec<String>,
-    pub task_info: Option<serde_json::Value>,
+    pub task_info: Option<TaskResultOutput>,
+}
+
+/// Arguments for fetching the result of a task
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct GetTaskResultArgs {
+    #[schemars(description = "Task ID returned by delegate_task")]
+    pub task_id: String,
+}
+
+/// Arguments for cancelling a task
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CancelTaskArgs {
+    #[schemars(description = "Task ID returned by delegate_task")]
+    pub task_id: String,
+}
+
+/// Arguments for a routing dry run
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct ExplainRouteArgs {
+    #[schemars(description = "Type of task, as passed to delegate_task")]
+    pub task_type: String,
+
+    #[schemars(description = "Prompt the task would be delegated with")]
+    pub prompt: String,
+
+    #[schemars(description = "Context the task would be delegated with, for rules with context conditions")]
+    pub context: Option<serde_json::Value>,
+}
+
+/// Arguments for listing agents; filters are case-insensitive
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct ListAgentsArgs {
+    #[schemars(description = "Only agents with this capability (e.g. 'code-generation')")]
+    pub capability: Option<String>,
+
+    #[schemars(description = "Only agents of this type (e.g. 'cli', 'prompt')")]
+    #[serde(rename = "type")]
+    pub agent_type: Option<String>,
+
+    #[schemars(description = "Only agents in this library category (e.g. 'engineering')")]
+    pub category: Option<String>,
+
+    #[schemars(description = "Include disabled agents")]
+    #[serde(default)]
+    pub include_disabled: bool,
+}
+
+impl ListAgentsArgs {
+    pub fn matches(&self, agent: &AgentConfig) -> bool {
+        let same = |filter: &Option<String>, value: Option<&str>| match filter {
+            Some(filter) => value.is_some_and(|value| value.eq_ignore_ascii_case(filter)),
+            None => true,
+        };
+
+        (self.include_disabled || agent.enabled)
+            && same(&self.agent_type, Some(&agent.agent_type))
+            && same(&self.category, agent.category.as_deref())
+            && self.capability.as_ref().is_none_or(|capability| {
+                agent.capabilities.iter().any(|c| c.eq_ignore_ascii_case(capability))
+            })
+    }
+}
+
+/// An agent with its remaining quota and current load
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AgentSummary {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub agent_type: String,
+    pub category: Option<String>,
+    pub capabilities: Vec<String>,
+    pub priority: u8,
+    pub enabled: bool,
+    pub remaining_per_day: u32,
+    pub remaining_per_minute: u32,
+    /// Pending and running tasks on the agent
+    pub active_tasks: usize,
+    pub max_concurrent_tasks: Option<usize>,
+}
+
+impl AgentSummary {
+    pub fn new(agent: &AgentConfig, active_tasks: usize, (daily, minute): (u32, u32)) -> Self {
+        Self {
+            id: agent.id.clone(),
+            name: agent.name.clone(),
+            agent_type: agent.agent_type.clone(),
+            category: agent.category.clone(),
+            capabilities: agent.capabilities.clone(),
+            priority: agent.priority,
+            enabled: agent.enabled,
+            remaining_per_day: daily,
+            remaining_per_minute: minute,
+            active_tasks,
+            max_concurrent_tasks: agent.max_concurrent_tasks,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListAgentsOutput {
+    /// Highest priority first, then by ID
+    pub agents: Vec<AgentSummary>,
+}
+
+/// Arguments for describing one agent
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct DescribeAgentArgs {
+    #[schemars(description = "Agent ID, as returned by list_agents")]
+    pub agent_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DescribeAgentOutput {
+    #[serde(flatten)]
+    pub agent: AgentSummary,
+    /// Agent config with `env` values redacted and `command`/`args` as written,
+    /// before `${VAR}` interpolation
+    pub config: AgentConfig,
+    /// `agent://{id}` resource of library agents
+    pub resource: Option<String>,
+    /// Rules that prefer the agent, in config order
+    pub routing_rules: Vec<AgentRule>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TaskResultOutput {
+    #[serde(flatten)]
+    pub task: TaskInfo,
+    pub duration_ms: Option<i64>,
+}
+
+impl From<&TaskInfo> for TaskResultOutput {
+    fn from(task: &TaskInfo) -> Self {
+        Self {
+            task: task.clone(),
+            duration_ms: task.duration_ms(),
+        }
+    }
 }
 
 impl Orchestrator {
@@ -72,39 +268,150 @@ impl Orchestrator {
             RateLimitTracker::new(config.rate_limiting.clone())
         ));
 
+        let admission = Arc::new(
+            AdmissionController::new(config.server.max_concurrent_tasks)
+        );
+
         let executor = Arc::new(
             AgentExecutor::new(
                 agent_registry.clone(),
                 rate_limiter.clone(),
+                admission.clone(),
                 config.routing.clone(),
+                config.rate_limiting.strategy,
             )
         );
 
         Ok(Self {
-            config,
+            commands: config.commands.clone(),
+            config: Arc::new(RwLock::new(config)),
             agent_registry,
             rate_limiter,
+            admission,
             executor,
+            resources_changed: watch::channel(()).0,
         })
     }
 
+    /// Watch the config's source files and hot-reload on change.
+    /// `load` re-reads and validates the config; if it fails the old config stays active.
+    pub async fn spawn_config_watcher<F>(&self, load: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn() -> Result<Config> + Send + 'static,
+    {
+        let config = self.config.clone();
+        let agent_registry = self.agent_registry.clone();
+        let executor = self.executor.clone();
+        let resources_changed = self.resources_changed.clone();
+        let mut watcher = ConfigWatcher::new(config.read().await.watched_paths());
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if !watcher.poll() {
+                    continue;
+                }
+
+                match load() {
+                    Ok(new_config) => {
+                        watcher.watch(new_config.watched_paths());
+                        apply_config(&config, &agent_registry, &executor, &resources_changed, new_config).await;
+                    }
+                    Err(e) => {
+                        tracing::error!("❌ Config reload failed, keeping the previous config: {:#}", e);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Routing dry run: which agent a task would go to, and why
+    pub async fn explain_route(
+        &self,
+        task_type: &str,
+        prompt: &str,
+        context: Option<&serde_json::Value>,
+    ) -> RouteExplanation {
+        self.executor.explain_route(task_type, prompt, context).await
+    }
+
+    /// Serve one MCP session on stdio until the client closes stdin
     pub async fn run_stdio(self) -> Result<()> {
+        self.serve_stream(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serve one MCP session over a byte stream until the client hangs up,
+    /// with every tool, resource and command prompt. Sessions served by one
+    /// orchestrator share its agents, tasks and rate limits.
+    pub async fn serve_stream<R, W>(&self, reader: R, writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + Sync + Debug + 'static,
+    {
+        let control = SessionControl::new(cancel_task_tool(self.agent_registry.clone()));
+        let server = self.server_builder(SessionKind::Stream, Some(control.clone())).build()?;
+        transport::run_session(server, control, reader, writer, self.resources_changed.subscribe()).await
+    }
+
+    /// Serve MCP streamable HTTP (responses and notifications over SSE) on
+    /// `addr`. Every client shares this orchestrator's agents, tasks and
+    /// rate-limit budget. HTTP sessions are not told when the agent library
+    /// changes, so resources do not advertise `listChanged` there.
+    ///
+    /// All clients share one server that handles a request at a time, so
+    /// `delegate_task` always runs in the background here: it returns the
+    /// task ID at once and `get_task_result` reports the outcome.
+    pub async fn start_http(&self, addr: SocketAddr) -> Result<(SocketAddr, tokio::task::JoinHandle<()>)> {
+        let server = Arc::new(tokio::sync::Mutex::new(self.server_builder(SessionKind::Http, None).build()?));
+        let mut middleware = ServerHttpMiddlewareChain::new();
+        middleware.add(Arc::new(resources::TemplatesMiddleware));
+        let config = StreamableHttpServerConfig {
+            http_middleware: Some(Arc::new(middleware)),
+            ..Default::default()
+        };
+        let (bound, handle) = StreamableHttpServer::with_config(addr, server, config).start().await?;
+        Ok((bound, handle))
+    }
+
+    /// Stream sessions pass the `control` their transport cancels calls through
+    fn server_builder(&self, kind: SessionKind, control: Option<SessionControl>) -> ServerBuilder {
         let executor = self.executor.clone();
         let agent_registry = self.agent_registry.clone();
+        let admission = self.admission.clone();
 
         // Build MCP server with typed tools
-        let server = ServerBuilder::new()
+        let builder = ServerBuilder::new()
             .name("gemini-mcp-proxy")
             .version("0.1.0")
+            .capabilities(ServerCapabilities {
+                resources: Some(ResourceCapabilities {
+                    subscribe: Some(false),
+                    list_changed: Some(kind == SessionKind::Stream),
+                }),
+                ..Default::default()
+            })
+            // Resources: agent library personas and their index
+            .resources(resources::AgentResources::new(agent_registry.clone()))
             // Tool: Delegate task to sub-agent
             .tool(
                 "delegate_task",
                 TypedTool::new("delegate_task", {
                     let executor = executor.clone();
-                    move |args: DelegateTaskArgs, _extra: RequestHandlerExtra| {
+                    move |mut args: DelegateTaskArgs, extra: RequestHandlerExtra| {
                         let executor = executor.clone();
+                        let control = control.clone();
+                        // A task waited on here would hold up every other HTTP client
+                        if kind == SessionKind::Http && !args.background {
+                            tracing::debug!("🌐 Running HTTP delegate_task in the background");
+                            args.background = true;
+                        }
                         Box::pin(async move {
-                            let output = executor.delegate_task(args).await?;
+                            // pmcp reads nothing while the call runs; the transport cancels it
+                            let _call = control
+                                .as_ref()
+                                .map(|control| control.track(&extra.request_id, extra.cancellation_token.clone()));
+                            let output = executor.delegate_task(args, extra.cancellation_token).await?;
                             Ok(serde_json::to_value(output)?)
                         })
                     }
@@ -116,38 +423,542 @@ impl Orchestrator {
                 "agent_status",
                 TypedTool::new("agent_status", {
                     let agent_registry = agent_registry.clone();
+                    let admission = admission.clone();
                     move |args: AgentStatusArgs, _extra: RequestHandlerExtra| {
                         let agent_registry = agent_registry.clone();
+                        let admission = admission.clone();
                         Box::pin(async move {
-                            let output = query_agent_status(agent_registry, args).await?;
+                            let output = query_agent_status(agent_registry, admission, args).await?;
                             Ok(serde_json::to_value(output)?)
                         })
                     }
                 })
                 .with_description("Get status of agents and running tasks")
             )
-            .build()?;
+            // Tool: Fetch task result
+            .tool(
+                "get_task_result",
+                TypedTool::new("get_task_result", {
+                    let agent_registry = agent_registry.clone();
+                    move |args: GetTaskResultArgs, _extra: RequestHandlerExtra| {
+                        let agent_registry = agent_registry.clone();
+                        Box::pin(async move {
+                            let output = get_task_result(agent_registry, &args.task_id).await?;
+                            Ok(serde_json::to_value(output)?)
+                        })
+                    }
+                })
+                .with_description("Get the status, result or error of a delegated task (including background tasks)")
+            )
+            // Tool: Cancel task (stream transports answer it themselves)
+            .tool(transport::CANCEL_TASK_TOOL, cancel_task_tool(agent_registry.clone()))
+            // Tool: Routing dry run
+            .tool(
+                "explain_route",
+                TypedTool::new("explain_route", {
+                    let executor = executor.clone();
+                    move |args: ExplainRouteArgs, _extra: RequestHandlerExtra| {
+                        let executor = executor.clone();
+                        Box::pin(async move {
+                            let output = executor.explain_route(&args.task_type, &args.prompt, args.context.as_ref()).await;
+                            Ok(serde_json::to_value(output)?)
+                        })
+                    }
+                })
+                .with_description("Explain which agent a task would be routed to and why, without running it or consuming quota")
+            )
+            // Tool: List agents
+            .tool(
+                "list_agents",
+                TypedTool::new("list_agents", {
+                    let executor = executor.clone();
+                    move |args: ListAgentsArgs, _extra: RequestHandlerExtra| {
+                        let executor = executor.clone();
+                        Box::pin(async move {
+                            let output = ListAgentsOutput { agents: executor.list_agents(&args).await };
+                            Ok(serde_json::to_value(output)?)
+                        })
+                    }
+                })
+                .with_description("List enabled agents with capabilities, priority, remaining quota and current load, optionally filtered by capability, type or category")
+            )
+            // Tool: Describe one agent
+            .tool(
+                "describe_agent",
+                TypedTool::new("describe_agent", {
+                    let executor = executor.clone();
+                    move |args: DescribeAgentArgs, _extra: RequestHandlerExtra| {
+                        let executor = executor.clone();
+                        Box::pin(async move {
+                            let output = executor
+                                .describe_agent(&args.agent_id)
+                                .await
+                                .ok_or_else(|| pmcp::Error::not_found(format!("Agent not found: {}", args.agent_id)))?;
+                            Ok(serde_json::to_value(output)?)
+                        })
+                    }
+                })
+                .with_description("Get the full config of one agent, its quota, load and the routing rules that prefer it")
+            );
+
+        // Prompts: commands/*.toml, re-read for every session
+        prompts::load(&self.commands)
+            .into_iter()
+            .fold(builder, |builder, prompt| builder.prompt(prompt.name.clone(), prompt))
+    }
+}
+
+/// Swap a validated config into the running server. Tasks in flight keep the
+/// agent config they started with, and usage counters are untouched.
+async fn apply_config(
+    config: &RwLock<Config>,
+    agent_registry: &RwLock<AgentRegistry>,
+    executor: &AgentExecutor,
+    resources_changed: &watch::Sender<()>,
+    mut new_config: Config,
+) {
+    let mut current = config.write().await;
+
+    // These are wired into long-lived state at startup
+    if new_config.server.max_concurrent_tasks != current.server.max_concurrent_tasks
+        || new_config.server.host != current.server.host
+        || new_config.server.port != current.server.port
+    {
+        tracing::warn!("⚠️  [server] changes take effect after a restart");
+    }
+    if new_config.rate_limiting.usage_db_path != current.rate_limiting.usage_db_path
+        || new_config.rate_limiting.track_usage != current.rate_limiting.track_usage
+    {
+        tracing::warn!("⚠️  Usage tracking changes take effect after a restart");
+    }
+    if new_config.commands.enabled != current.commands.enabled
+        || new_config.commands.path != current.commands.path
+    {
+        tracing::warn!("⚠️  [commands] changes take effect after a restart");
+    }
+    new_config.server = current.server.clone();
+    new_config.rate_limiting.usage_db_path = current.rate_limiting.usage_db_path.clone();
+    new_config.rate_limiting.track_usage = current.rate_limiting.track_usage;
+    new_config.commands = current.commands.clone();
+
+    // Agents (with their rate limits) and routing change together
+    let mut registry = agent_registry.write().await;
+    executor.replace_router(new_config.routing.clone(), new_config.rate_limiting.strategy);
+    registry.replace_agents(new_config.agents.clone());
+    drop(registry);
 
-        // Run the MCP server on stdio
-        server.run_stdio().await?;
-        Ok(())
+    tracing::info!(
+        "🔄 Config reloaded: {} agents, {} routing rules",
+        new_config.agents.len(),
+        new_config.routing.rules.len()
+    );
+    if resources::snapshot(&current.agents) != resources::snapshot(&new_config.agents) {
+        tracing::info!("📚 Agent library changed, notifying clients");
+        resources_changed.send_replace(());
     }
+    *current = new_config;
 }
 
 async fn query_agent_status(
     registry: Arc<RwLock<AgentRegistry>>,
+    admission: Arc<AdmissionController>,
     args: AgentStatusArgs,
 ) -> pmcp::Result<AgentStatusOutput> {
+    let task_info = match &args.task_id {
+        Some(task_id) => Some(get_task_result(registry.clone(), task_id).await?),
+        None => None,
+    };
+
     let registry = registry.read().await;
 
     Ok(AgentStatusOutput {
         active_tasks: registry.active_task_count(),
+        queued_tasks: admission.queue_depth(),
+        running_tasks: admission.running(),
+        max_concurrent_tasks: admission.max_concurrent(),
         available_agents: registry.list_agent_ids(),
-        task_info: args.task_id.map(|id| {
-            serde_json::json!({
-                "task_id": id,
-                "status": "pending"
-            })
-        }),
+        task_info,
     })
 }
+
+async fn get_task_result(
+    registry: Arc<RwLock<AgentRegistry>>,
+    task_id: &str,
+) -> pmcp::Result<TaskResultOutput> {
+    let registry = registry.read().await;
+
+    registry
+        .get_task(task_id)
+        .map(TaskResultOutput::from)
+        .ok_or_else(|| pmcp::Error::not_found(format!("Task not found: {}", task_id)))
+}
+
+/// The `cancel_task` tool
+fn cancel_task_tool(agent_registry: Arc<RwLock<AgentRegistry>>) -> impl ToolHandler + 'static {
+    TypedTool::new(transport::CANCEL_TASK_TOOL, move |args: CancelTaskArgs, _extra: RequestHandlerExtra| {
+        let agent_registry = agent_registry.clone();
+        Box::pin(async move {
+            let output = cancel_task(agent_registry, &args.task_id).await?;
+            Ok(serde_json::to_value(output)?)
+        })
+    })
+    .with_description("Cancel a pending or running task and terminate its agent process")
+}
+
+async fn cancel_task(
+    registry: Arc<RwLock<AgentRegistry>>,
+    task_id: &str,
+) -> pmcp::Result<TaskResultOutput> {
+    let mut registry = registry.write().await;
+
+    if registry.get_task(task_id).is_none() {
+        return Err(pmcp::Error::not_found(format!("Task not found: {}", task_id)));
+    }
+
+    registry
+        .cancel_task(task_id)
+        .map_err(|e| pmcp::Error::invalid_state(e.to_string()))?;
+
+    tracing::info!("🛑 Cancellation requested for task {}", task_id);
+
+    registry
+        .get_task(task_id)
+        .map(TaskResultOutput::from)
+        .ok_or_else(|| pmcp::Error::not_found(format!("Task not found: {}", task_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RateLimit;
+    use tempfile::TempDir;
+
+    fn test_config(temp: &TempDir, agents: &[&str], max_concurrent_tasks: usize) -> Config {
+        let mut config: Config = toml::from_str(&format!(r#"
+            agents = []
+
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+            max_concurrent_tasks = {}
+
+            [main_agent]
+            name = "gemini"
+            type = "gemini-cli"
+
+            [routing]
+            rules = []
+
+            [rate_limiting]
+            usage_db_path = "{}"
+
+            [logging]
+
+            [agent_library]
+            enabled = false
+        "#, max_concurrent_tasks, temp.path().join("usage.db").display())).unwrap();
+
+        config.agents = agents
+            .iter()
+            .map(|id| crate::config::AgentConfig {
+                id: id.to_string(),
+                name: id.to_string(),
+                agent_type: "cli".to_string(),
+                command: Some("true".to_string()),
+                enabled: true,
+                ..Default::default()
+            })
+            .collect();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_agents_and_keeps_state() {
+        let temp = TempDir::new().unwrap();
+        let orchestrator = Orchestrator::new(test_config(&temp, &["old"], 5)).await.unwrap();
+
+        orchestrator.agent_registry.write().await.register_task(TaskInfo::new(
+            "task-1".to_string(),
+            "old".to_string(),
+            "test".to_string(),
+        ));
+        assert!(orchestrator.rate_limiter.write().await
+            .check_and_increment("old", &RateLimit::default()).await);
+
+        apply_config(
+            &orchestrator.config,
+            &orchestrator.agent_registry,
+            &orchestrator.executor,
+            &orchestrator.resources_changed,
+            test_config(&temp, &["new"], 9),
+        ).await;
+
+        let registry = orchestrator.agent_registry.read().await;
+        assert_eq!(registry.list_agent_ids(), vec!["new".to_string()]);
+        assert!(registry.get_task("task-1").is_some());
+
+        let usage = orchestrator.rate_limiter.read().await.get_usage("old");
+        assert_eq!(usage, Some((1, 1)));
+
+        // Restart-only settings keep their running values
+        assert_eq!(orchestrator.config.read().await.server.max_concurrent_tasks, 5);
+    }
+
+    #[tokio::test]
+    async fn test_session_serves_resources_and_list_changed() {
+        use serde_json::{json, Value};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let temp = TempDir::new().unwrap();
+        let orchestrator = Orchestrator::new(test_config(&temp, &["cli-agent"], 5)).await.unwrap();
+        let (client, server_side) = tokio::io::duplex(64 * 1024);
+        let (reader, writer) = tokio::io::split(server_side);
+        let orchestrator = Arc::new(orchestrator);
+        let session = tokio::spawn({
+            let orchestrator = orchestrator.clone();
+            async move { orchestrator.serve_stream(reader, writer).await }
+        });
+
+        let (client_reader, mut client_writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(client_reader).lines();
+        let messages = [
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "1.0.0" },
+                },
+            }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "resources/templates/list", "params": {} }),
+        ];
+        for message in messages {
+            client_writer.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        }
+
+        async fn next<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut tokio::io::Lines<R>) -> Value {
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+        }
+        let initialized = next(&mut lines).await;
+        assert_eq!(initialized["result"]["capabilities"]["resources"]["listChanged"], true);
+        let templates = next(&mut lines).await;
+        assert_eq!(templates["result"]["resourceTemplates"][0]["uriTemplate"], "agent://{id}");
+
+        // A persona appears on disk: the client is told without asking
+        let mut new_config = test_config(&temp, &["cli-agent"], 5);
+        new_config.agents.push(crate::config::AgentConfig {
+            id: "pirate".to_string(),
+            name: "Pirate".to_string(),
+            agent_type: crate::agents::library::PROMPT_AGENT_TYPE.to_string(),
+            enabled: true,
+            persona: Some(crate::config::AgentPersona {
+                system_prompt: "Talk like a pirate.".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        apply_config(
+            &orchestrator.config,
+            &orchestrator.agent_registry,
+            &orchestrator.executor,
+            &orchestrator.resources_changed,
+            new_config,
+        ).await;
+        assert_eq!(next(&mut lines).await["method"], "notifications/resources/list_changed");
+
+        let read = json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/read", "params": { "uri": "agent://pirate" } });
+        client_writer.write_all(format!("{}\n", read).as_bytes()).await.unwrap();
+        let response = next(&mut lines).await;
+        let text = response["result"]["contents"][0]["text"].as_str().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(text).unwrap()["system_prompt"], "Talk like a pirate.");
+
+        // Closing the input ends the session
+        client_writer.shutdown().await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), session).await.unwrap().unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_session_cancels_synchronous_task() {
+        use crate::agents::register::TaskStatus;
+        use serde_json::{json, Value};
+        use std::time::Duration;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let temp = TempDir::new().unwrap();
+        let mut config = test_config(&temp, &[], 5);
+        config.agents.push(crate::config::AgentConfig {
+            id: "sleeper".to_string(),
+            name: "sleeper".to_string(),
+            agent_type: "cli".to_string(),
+            command: Some("sleep".to_string()),
+            args: Some(vec!["30".to_string()]),
+            enabled: true,
+            ..Default::default()
+        });
+        let orchestrator = Arc::new(Orchestrator::new(config).await.unwrap());
+        let (client, server_side) = tokio::io::duplex(64 * 1024);
+        let (reader, writer) = tokio::io::split(server_side);
+        let session = tokio::spawn({
+            let orchestrator = orchestrator.clone();
+            async move { orchestrator.serve_stream(reader, writer).await }
+        });
+
+        let (client_reader, mut client_writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(client_reader).lines();
+        async fn send<W: tokio::io::AsyncWrite + Unpin>(writer: &mut W, message: Value) {
+            writer.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        }
+        async fn next<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut tokio::io::Lines<R>) -> Value {
+            let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await;
+            serde_json::from_str(&line.expect("no response in time").unwrap().unwrap()).unwrap()
+        }
+        // ID of the task once the synchronous call below has started it
+        let running_task = || async {
+            for _ in 0..100 {
+                let registry = orchestrator.agent_registry.read().await;
+                if let Some(task) = registry.tasks().find(|task| task.status == TaskStatus::Running) {
+                    return task.task_id.clone();
+                }
+                drop(registry);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            panic!("the task never started");
+        };
+        let delegate = |id: u64| json!({
+            "jsonrpc": "2.0", "id": id, "method": "tools/call",
+            "params": { "name": "delegate_task", "arguments": { "task_type": "test", "prompt": "hi", "agent_id": "sleeper" } },
+        });
+
+        send(&mut client_writer, json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": { "protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": { "name": "test", "version": "1.0.0" } },
+        })).await;
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+        assert_eq!(next(&mut lines).await["id"], 1);
+
+        // The client gives up on the call
+        send(&mut client_writer, delegate(2)).await;
+        let task_id = running_task().await;
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": 2 } })).await;
+        let response = next(&mut lines).await;
+        assert_eq!(response["id"], 2);
+        assert!(response["error"].is_object(), "{}", response);
+        let registry = orchestrator.agent_registry.read().await;
+        assert_eq!(registry.get_task(&task_id).unwrap().status, TaskStatus::Cancelled);
+        drop(registry);
+
+        // cancel_task is answered while the call it stops is still running
+        send(&mut client_writer, delegate(3)).await;
+        let task_id = running_task().await;
+        send(&mut client_writer, json!({
+            "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+            "params": { "name": "cancel_task", "arguments": { "task_id": task_id } },
+        })).await;
+        let cancelled = next(&mut lines).await;
+        assert_eq!(cancelled["id"], 4);
+        let text = cancelled["result"]["content"][0]["text"].as_str().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(text).unwrap()["status"], "cancelled");
+        let response = next(&mut lines).await;
+        assert_eq!(response["id"], 3);
+        assert!(response["error"].is_object(), "{}", response);
+
+        session.abort();
+    }
+
+    /// One HTTP/1.1 POST on a fresh connection, returning the raw response
+    async fn http_post(addr: SocketAddr, session_id: Option<&str>, body: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let session_header = session_id
+            .map(|id| format!("Mcp-Session-Id: {}\r\n", id))
+            .unwrap_or_default();
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nAccept: application/json, text/event-stream\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            addr, session_header, body.len(), body
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// Initialize an HTTP session and return its ID
+    async fn http_session(addr: SocketAddr) -> String {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0.0"}}}"#;
+        let response = http_post(addr, None, body).await;
+        response
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("mcp-session-id").then(|| value.trim().to_string())
+            })
+            .unwrap_or_else(|| panic!("no session ID in {}", response))
+    }
+
+    #[tokio::test]
+    async fn test_http_transport_serves_tools() {
+        let temp = TempDir::new().unwrap();
+        let orchestrator = Orchestrator::new(test_config(&temp, &["http-agent"], 5)).await.unwrap();
+        let (addr, server) = orchestrator.start_http("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0.0"}}}"#;
+        let response = http_post(addr, None, body).await;
+
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.to_lowercase().contains("mcp-session-id"));
+        assert!(response.contains("\"serverInfo\""));
+
+        // Templates are filled in over HTTP too
+        let session_id = http_session(addr).await;
+        let body = r#"{"jsonrpc":"2.0","id":2,"method":"resources/templates/list","params":{}}"#;
+        let response = http_post(addr, Some(&session_id), body).await;
+        assert!(response.contains("agent://{id}"), "{}", response);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_http_delegate_task_does_not_block_other_clients() {
+        let temp = TempDir::new().unwrap();
+        let mut config = test_config(&temp, &["slow-agent"], 5);
+        config.agents[0].command = Some("sh".to_string());
+        config.agents[0].args = Some(vec![
+            "-c".to_string(),
+            r#"read line; sleep 10; echo '{"jsonrpc":"2.0","id":1,"result":"done"}'"#.to_string(),
+        ]);
+        let orchestrator = Orchestrator::new(config).await.unwrap();
+        let (addr, server) = orchestrator.start_http("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let first = http_session(addr).await;
+        let second = http_session(addr).await;
+
+        // A sync delegate_task on one client...
+        let delegate = tokio::spawn(async move {
+            let body = r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"delegate_task","arguments":{"task_type":"code","prompt":"slow","agent_id":"slow-agent"}}}"#;
+            http_post(addr, Some(&first), body).await
+        });
+
+        // ...leaves the other one free while the agent runs
+        while orchestrator.agent_registry.read().await.active_task_count() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let body = r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"agent_status","arguments":{}}}"#;
+        let status = tokio::time::timeout(
+            std::time::Duration::from_secs(3),
+            http_post(addr, Some(&second), body),
+        ).await.expect("agent_status waited for delegate_task");
+        assert!(status.contains("slow-agent"), "{}", status);
+
+        // The delegating client gets the task ID back instead of waiting
+        let delegated = tokio::time::timeout(std::time::Duration::from_secs(3), delegate)
+            .await
+            .expect("delegate_task waited for the agent")
+            .unwrap();
+        assert!(delegated.contains("task_id"), "{}", delegated);
+        assert!(!delegated.contains("done"), "{}", delegated);
+        server.abort();
+    }
+}
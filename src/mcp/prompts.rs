@@ -0,0 +1,293 @@
+//! Gemini extension commands (`commands/**/*.toml`) as MCP prompts.
+//!
+//! `commands/agent/info.toml` becomes prompt `agent:info`, like the
+//! `/agent:info` slash command Gemini CLI makes of it. `{{args}}` and
+//! `${extensionPath}` are filled in here, so every MCP client gets the same
+//! text Gemini would.
+
+use crate::config::{expand_tilde, CommandsConfig};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use pmcp::types::{Content, GetPromptResult, PromptArgument, PromptArgumentType, PromptInfo, PromptMessage, Role};
+use pmcp::{PromptHandler, RequestHandlerExtra};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ARGS_PLACEHOLDER: &str = "{{args}}";
+const EXTENSION_PATH_PLACEHOLDER: &str = "${extensionPath}";
+
+/// One command file. Gemini only reads `description` and `prompt`;
+/// `arguments` names and types what `{{args}}` stands for.
+#[derive(Debug, Deserialize)]
+struct CommandFile {
+    description: Option<String>,
+    prompt: String,
+    #[serde(default)]
+    arguments: Vec<CommandArgument>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandArgument {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, rename = "type")]
+    pub arg_type: PromptArgumentType,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandPrompt {
+    pub name: String,
+    description: Option<String>,
+    template: String,
+    arguments: Vec<CommandArgument>,
+    extension_path: String,
+}
+
+impl CommandPrompt {
+    fn parse(name: String, content: &str, extension_path: &Path) -> Result<Self> {
+        let file: CommandFile = toml::from_str(content)?;
+
+        // A template that takes `{{args}}` without declaring arguments gets
+        // Gemini's single free-form one
+        let arguments = if file.arguments.is_empty() && file.prompt.contains(ARGS_PLACEHOLDER) {
+            vec![CommandArgument {
+                name: "args".to_string(),
+                description: Some("Text after the command".to_string()),
+                required: false,
+                arg_type: PromptArgumentType::String,
+            }]
+        } else {
+            file.arguments
+        };
+
+        Ok(Self {
+            name,
+            description: file.description,
+            template: file.prompt,
+            arguments,
+            extension_path: extension_path.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// The prompt text for `args`. Declared arguments fill `{{args}}` in
+    /// order, separated by spaces, as if typed after the slash command.
+    pub fn render(&self, args: &HashMap<String, String>) -> pmcp::Result<String> {
+        if let Some(unknown) = args.keys().find(|name| !self.arguments.iter().any(|a| &a.name == *name)) {
+            return Err(pmcp::Error::invalid_params(format!(
+                "Prompt '{}' has no argument '{}'",
+                self.name, unknown
+            )));
+        }
+
+        let mut values = Vec::new();
+        for argument in &self.arguments {
+            match args.get(&argument.name).map(|value| value.trim()).filter(|value| !value.is_empty()) {
+                Some(value) => {
+                    argument.arg_type.parse_value(value).map_err(|e| {
+                        pmcp::Error::invalid_params(format!("Argument '{}': {}", argument.name, e))
+                    })?;
+                    values.push(value);
+                }
+                None if argument.required => {
+                    return Err(pmcp::Error::invalid_params(format!(
+                        "Prompt '{}' requires argument '{}'",
+                        self.name, argument.name
+                    )));
+                }
+                None => {}
+            }
+        }
+
+        Ok(self.template
+            .replace(EXTENSION_PATH_PLACEHOLDER, &self.extension_path)
+            .replace(ARGS_PLACEHOLDER, &values.join(" ")))
+    }
+}
+
+#[async_trait]
+impl PromptHandler for CommandPrompt {
+    async fn handle(&self, args: HashMap<String, String>, _extra: RequestHandlerExtra) -> pmcp::Result<GetPromptResult> {
+        Ok(GetPromptResult {
+            description: self.description.clone(),
+            messages: vec![PromptMessage {
+                role: Role::User,
+                content: Content::Text { text: self.render(&args)? },
+            }],
+        })
+    }
+
+    fn metadata(&self) -> Option<PromptInfo> {
+        Some(PromptInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            arguments: Some(
+                self.arguments
+                    .iter()
+                    .map(|argument| PromptArgument {
+                        name: argument.name.clone(),
+                        description: argument.description.clone(),
+                        required: argument.required,
+                        completion: None,
+                        arg_type: Some(argument.arg_type),
+                    })
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// Every command under the configured directory. Problems with single files
+/// are logged and skipped.
+pub fn load(config: &CommandsConfig) -> Vec<CommandPrompt> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    let dir = expand_tilde(&config.path);
+    if !dir.is_dir() {
+        tracing::debug!("Commands directory {:?} not found, skipping", dir);
+        return Vec::new();
+    }
+
+    // Prompts are used from anywhere, so `${extensionPath}` is absolute
+    let dir = dir.canonicalize().unwrap_or(dir);
+    let extension_path = dir.parent().map(Path::to_path_buf).unwrap_or_else(|| dir.clone());
+
+    let mut prompts = Vec::new();
+    for path in command_files(&dir) {
+        match load_command(&dir, &path, &extension_path) {
+            Ok(prompt) => prompts.push(prompt),
+            Err(e) => tracing::warn!("⚠️  Skipping command {:?}: {:#}", path, e),
+        }
+    }
+    prompts
+}
+
+fn load_command(dir: &Path, path: &Path, extension_path: &Path) -> Result<CommandPrompt> {
+    let content = fs::read_to_string(path)?;
+    let name = command_name(dir, path).context("Command path is not valid UTF-8")?;
+    CommandPrompt::parse(name, &content, extension_path)
+}
+
+/// `agent/info.toml` → `agent:info`
+fn command_name(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?.with_extension("");
+    let parts: Option<Vec<&str>> = relative.components().map(|c| c.as_os_str().to_str()).collect();
+    Some(parts?.join(":"))
+}
+
+/// `*.toml` files under `dir`, recursively, sorted
+fn command_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        if path.is_dir() {
+            files.extend(command_files(&path));
+        } else if path.extension().is_some_and(|ext| ext == "toml") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render_arguments() {
+        let prompt = CommandPrompt::parse(
+            "agent:info".to_string(),
+            r#"
+                description = "Info"
+                prompt = "python3 ${extensionPath}/scripts/agent_manager.py info {{args}}"
+
+                [[arguments]]
+                name = "agent_id"
+                required = true
+
+                [[arguments]]
+                name = "limit"
+                type = "integer"
+            "#,
+            Path::new("/opt/ext"),
+        ).unwrap();
+
+        assert_eq!(
+            prompt.render(&args(&[("agent_id", "pirate")])).unwrap(),
+            "python3 /opt/ext/scripts/agent_manager.py info pirate"
+        );
+        assert_eq!(
+            prompt.render(&args(&[("agent_id", "pirate"), ("limit", "3")])).unwrap(),
+            "python3 /opt/ext/scripts/agent_manager.py info pirate 3"
+        );
+        assert!(prompt.render(&args(&[])).is_err());
+        assert!(prompt.render(&args(&[("agent_id", "pirate"), ("limit", "many")])).is_err());
+        assert!(prompt.render(&args(&[("agent", "pirate")])).is_err());
+
+        // Undeclared `{{args}}` is one optional free-form argument
+        let free = CommandPrompt::parse("free".to_string(), "prompt = \"Do {{args}}.\"", Path::new("/x")).unwrap();
+        assert_eq!(free.render(&args(&[("args", "this and that")])).unwrap(), "Do this and that.");
+        assert_eq!(free.render(&args(&[])).unwrap(), "Do .");
+    }
+
+    #[test]
+    fn test_load_names_commands_like_gemini() {
+        let temp = TempDir::new().unwrap();
+        let commands = temp.path().join("commands");
+        fs::create_dir_all(commands.join("agent")).unwrap();
+        fs::write(commands.join("system-agent.toml"), "prompt = \"cat ${extensionPath}/agents/agents.json\"").unwrap();
+        fs::write(commands.join("agent/info.toml"), "prompt = \"info {{args}}\"").unwrap();
+        fs::write(commands.join("agent/broken.toml"), "description = \"no prompt\"").unwrap();
+        fs::write(commands.join("agent/notes.md"), "not a command").unwrap();
+
+        let config = CommandsConfig {
+            enabled: true,
+            path: commands.to_string_lossy().into_owned(),
+        };
+        let prompts = load(&config);
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["agent:info", "system-agent"]);
+
+        let root = temp.path().canonicalize().unwrap();
+        assert_eq!(
+            prompts[1].render(&args(&[])).unwrap(),
+            format!("cat {}/agents/agents.json", root.display())
+        );
+    }
+
+    #[test]
+    fn test_load_bundled_commands() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = CommandsConfig {
+            enabled: true,
+            path: root.join("commands").to_string_lossy().into_owned(),
+        };
+        let prompts = load(&config);
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["agent:examples", "agent:info", "agent:new", "agent:switch", "system-agent"]);
+
+        for prompt in &prompts {
+            let arguments = prompt.metadata().unwrap().arguments.unwrap();
+            let filled: HashMap<String, String> = arguments
+                .iter()
+                .map(|argument| (argument.name.clone(), "pirate".to_string()))
+                .collect();
+            let text = prompt.render(&filled).unwrap();
+            assert!(!text.contains(ARGS_PLACEHOLDER) && !text.contains(EXTENSION_PATH_PLACEHOLDER), "{}", prompt.name);
+        }
+    }
+}
@@ -114,6 +114,7 @@ pub mod error_codes {
     pub const RATE_LIMIT_EXCEEDED: i32 = -32000;
     pub const AGENT_NOT_FOUND: i32 = -32001;
     pub const AGENT_UNAVAILABLE: i32 = -32002;
+    pub const AGENT_TIMEOUT: i32 = -32003;
 }
 
 impl JsonRpcResponse {
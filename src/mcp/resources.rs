@@ -0,0 +1,234 @@
+//! Agent library as MCP resources: `agents://index` lists the library and
+//! `agent://{id}` is one persona's system prompt and metadata
+
+use crate::agents::AgentRegistry;
+use crate::config::AgentConfig;
+use async_trait::async_trait;
+use pmcp::server::http_middleware::{ServerHttpContext, ServerHttpMiddleware, ServerHttpResponse};
+use pmcp::types::{Content, ListResourcesResult, ReadResourceResult, ResourceInfo, ResourceTemplate};
+use pmcp::{RequestHandlerExtra, ResourceHandler};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const INDEX_URI: &str = "agents://index";
+const AGENT_URI_PREFIX: &str = "agent://";
+const MIME_TYPE: &str = "application/json";
+
+pub fn agent_uri(id: &str) -> String {
+    format!("{}{}", AGENT_URI_PREFIX, id)
+}
+
+/// `agent://{id}` also reaches custom agents added after the client listed resources
+pub fn templates() -> Vec<ResourceTemplate> {
+    vec![ResourceTemplate {
+        uri_template: format!("{}{{id}}", AGENT_URI_PREFIX),
+        name: "Library agent".to_string(),
+        description: Some("System prompt and metadata of a built-in or custom library agent, by ID".to_string()),
+        mime_type: Some(MIME_TYPE.to_string()),
+    }]
+}
+
+/// Fills in `resources/templates/list` answers over HTTP, which pmcp's server
+/// always answers empty (stream sessions get them from the transport)
+pub struct TemplatesMiddleware;
+
+#[async_trait]
+impl ServerHttpMiddleware for TemplatesMiddleware {
+    async fn on_response(&self, response: &mut ServerHttpResponse, _context: &ServerHttpContext) -> pmcp::Result<()> {
+        let Ok(mut message) = serde_json::from_slice::<Value>(&response.body) else {
+            return Ok(());
+        };
+        let Some(list) = message.pointer_mut("/result/resourceTemplates") else {
+            return Ok(());
+        };
+        *list = serde_json::to_value(templates())?;
+        response.body = serde_json::to_vec(&message)?;
+        Ok(())
+    }
+}
+
+/// Catalog entry of each library agent, sorted by ID
+pub fn index(agents: &[&AgentConfig]) -> Value {
+    let mut agents: Vec<&AgentConfig> = agents.iter().copied().filter(|agent| agent.persona.is_some()).collect();
+    agents.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let entries: Vec<Value> = agents
+        .iter()
+        .map(|agent| json!({
+            "id": agent.id,
+            "name": agent.name,
+            "description": agent.description,
+            "category": agent.category,
+            "origin": agent.persona.as_ref().map(|persona| persona.origin),
+            "enabled": agent.enabled,
+            "uri": agent_uri(&agent.id),
+        }))
+        .collect();
+    json!({ "agents": entries })
+}
+
+/// Full document of a library agent; `None` for agents from the config file
+pub fn agent_document(agent: &AgentConfig) -> Option<Value> {
+    let persona = agent.persona.as_ref()?;
+    Some(json!({
+        "id": agent.id,
+        "name": agent.name,
+        "description": agent.description,
+        "category": agent.category,
+        "capabilities": agent.capabilities,
+        "enabled": agent.enabled,
+        "origin": persona.origin,
+        "source": persona.source,
+        "runner": persona.runner,
+        "tools": persona.tools,
+        "color": persona.color,
+        "use_cases": persona.use_cases,
+        "system_prompt": persona.system_prompt,
+    }))
+}
+
+/// Every resource document, to tell whether a reload changed the library
+pub fn snapshot(agents: &[AgentConfig]) -> Vec<Value> {
+    let mut documents: Vec<(&str, Value)> = agents
+        .iter()
+        .filter_map(|agent| Some((agent.id.as_str(), agent_document(agent)?)))
+        .collect();
+    documents.sort_by(|a, b| a.0.cmp(b.0));
+    documents.into_iter().map(|(_, document)| document).collect()
+}
+
+/// Serves resources from the live registry, so reloads show up right away
+pub struct AgentResources {
+    agent_registry: Arc<RwLock<AgentRegistry>>,
+}
+
+impl AgentResources {
+    pub fn new(agent_registry: Arc<RwLock<AgentRegistry>>) -> Self {
+        Self { agent_registry }
+    }
+}
+
+#[async_trait]
+impl ResourceHandler for AgentResources {
+    async fn read(&self, uri: &str, _extra: RequestHandlerExtra) -> pmcp::Result<ReadResourceResult> {
+        let registry = self.agent_registry.read().await;
+
+        let document = if uri == INDEX_URI {
+            index(&registry.get_agents_by_priority())
+        } else {
+            uri.strip_prefix(AGENT_URI_PREFIX)
+                .and_then(|id| registry.get_agent(id))
+                .and_then(agent_document)
+                .ok_or_else(|| pmcp::Error::not_found(uri))?
+        };
+
+        Ok(ReadResourceResult {
+            contents: vec![Content::Resource {
+                uri: uri.to_string(),
+                text: Some(serde_json::to_string_pretty(&document)?),
+                mime_type: Some(MIME_TYPE.to_string()),
+            }],
+        })
+    }
+
+    async fn list(&self, _cursor: Option<String>, _extra: RequestHandlerExtra) -> pmcp::Result<ListResourcesResult> {
+        let registry = self.agent_registry.read().await;
+        let mut agents: Vec<&AgentConfig> = registry
+            .get_agents_by_priority()
+            .into_iter()
+            .filter(|agent| agent.persona.is_some())
+            .collect();
+        agents.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut resources = vec![ResourceInfo {
+            uri: INDEX_URI.to_string(),
+            name: "Agent library index".to_string(),
+            description: Some("Every built-in and custom library agent".to_string()),
+            mime_type: Some(MIME_TYPE.to_string()),
+        }];
+        resources.extend(agents.into_iter().map(|agent| ResourceInfo {
+            uri: agent_uri(&agent.id),
+            name: agent.name.clone(),
+            description: agent.description.clone(),
+            mime_type: Some(MIME_TYPE.to_string()),
+        }));
+
+        Ok(ListResourcesResult { resources, next_cursor: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AgentOrigin, AgentPersona};
+
+    fn persona_agent(id: &str, origin: AgentOrigin) -> AgentConfig {
+        AgentConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            agent_type: crate::agents::library::PROMPT_AGENT_TYPE.to_string(),
+            enabled: true,
+            persona: Some(AgentPersona {
+                system_prompt: format!("You are {}.", id),
+                origin,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn extra() -> RequestHandlerExtra {
+        RequestHandlerExtra::new("test".to_string(), tokio_util::sync::CancellationToken::new())
+    }
+
+    #[tokio::test]
+    async fn test_read_and_list() {
+        let cli = AgentConfig {
+            id: "qwen-coder".to_string(),
+            agent_type: "cli".to_string(),
+            ..Default::default()
+        };
+        let agents = vec![persona_agent("reviewer", AgentOrigin::BuiltIn), persona_agent("pirate", AgentOrigin::Custom), cli];
+        let resources = AgentResources::new(Arc::new(RwLock::new(AgentRegistry::new(agents))));
+
+        let listed = resources.list(None, extra()).await.unwrap();
+        let uris: Vec<&str> = listed.resources.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(uris, vec![INDEX_URI, "agent://pirate", "agent://reviewer"]);
+
+        let read = |uri: &'static str| {
+            let resources = &resources;
+            async move {
+                let result = resources.read(uri, extra()).await?;
+                let Content::Resource { text: Some(text), .. } = &result.contents[0] else {
+                    panic!("expected a text resource");
+                };
+                pmcp::Result::Ok(serde_json::from_str::<Value>(text).unwrap())
+            }
+        };
+
+        let pirate = read("agent://pirate").await.unwrap();
+        assert_eq!(pirate["system_prompt"], "You are pirate.");
+        assert_eq!(pirate["origin"], "custom");
+
+        let index = read(INDEX_URI).await.unwrap();
+        assert_eq!(index["agents"].as_array().unwrap().len(), 2);
+        assert_eq!(index["agents"][1]["uri"], "agent://reviewer");
+
+        // Configured CLI agents have no persona to serve
+        assert!(read("agent://qwen-coder").await.is_err());
+        assert!(read("agent://missing").await.is_err());
+    }
+
+    #[test]
+    fn test_snapshot_tracks_personas_only() {
+        let mut agents = vec![persona_agent("reviewer", AgentOrigin::BuiltIn)];
+        let before = snapshot(&agents);
+
+        agents.push(AgentConfig { id: "qwen-coder".to_string(), ..Default::default() });
+        assert_eq!(snapshot(&agents), before);
+
+        agents[0].persona.as_mut().unwrap().system_prompt = "Review harder.".to_string();
+        assert_ne!(snapshot(&agents), before);
+    }
+}
@@ -0,0 +1,379 @@
+//! MCP sessions over byte streams (stdio, Unix sockets)
+//!
+//! pmcp 1.9 holds its transport while waiting for the next message, so a
+//! server notification would sit in its queue until the client sends
+//! something. Here messages are read on a separate task and the transport
+//! pushes `notifications/resources/list_changed` itself. It also answers
+//! `resources/templates/list`, which pmcp's server always answers empty.
+//!
+//! pmcp reads nothing else while a tool call runs, so the reader task
+//! handles `notifications/cancelled` and `cancel_task` on its own; queued
+//! behind the call they stop, they would never get through.
+
+use super::resources;
+use async_trait::async_trait;
+use pmcp::error::TransportError;
+use pmcp::shared::{StdioTransport, Transport, TransportMessage};
+use pmcp::types::jsonrpc::{JSONRPCError, ResponsePayload};
+use pmcp::types::{
+    CallToolResult, ClientNotification, ClientRequest, Content, JSONRPCResponse, ListResourceTemplatesResult,
+    Notification, Request, RequestId, ServerNotification,
+};
+use pmcp::{RequestHandlerExtra, Server, ToolHandler};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+/// Largest message accepted from a client, in either framing
+const MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+
+/// Tool that `SessionControl` runs on the reader task
+pub const CANCEL_TASK_TOOL: &str = "cancel_task";
+
+/// A message and whether the client sent it as one JSON per line
+type Frame = pmcp::Result<(TransportMessage, bool)>;
+
+/// Serve `server` until the client hangs up. `resources_changed` fires when
+/// the agent library changes; `control` must be the one `server`'s tools
+/// track their calls with.
+pub async fn run_session<R, W>(
+    server: Server,
+    control: SessionControl,
+    reader: R,
+    writer: W,
+    resources_changed: watch::Receiver<()>,
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + Sync + Debug + 'static,
+{
+    let closed = CancellationToken::new();
+    let transport = StreamTransport::new(reader, writer, control, resources_changed, closed.clone());
+
+    // `Server::run` never returns on its own; the transport reports the hang-up
+    tokio::select! {
+        result = server.run(transport) => result?,
+        _ = closed.cancelled() => {}
+    }
+    Ok(())
+}
+
+/// Cancellation for one stream session, handled on its reader task
+#[derive(Clone)]
+pub struct SessionControl {
+    /// Tokens of the running tool calls, by JSON-RPC request ID
+    calls: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    cancel_task: Arc<dyn ToolHandler>,
+}
+
+impl SessionControl {
+    /// `cancel_task` is the session's `CANCEL_TASK_TOOL`
+    pub fn new(cancel_task: impl ToolHandler + 'static) -> Self {
+        Self {
+            calls: Arc::default(),
+            cancel_task: Arc::new(cancel_task),
+        }
+    }
+
+    /// Let `notifications/cancelled` for `request_id` fire `token` until the
+    /// returned guard is dropped
+    pub fn track(&self, request_id: &str, token: CancellationToken) -> TrackedCall {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).insert(request_id.to_string(), token);
+        TrackedCall {
+            calls: self.calls.clone(),
+            request_id: request_id.to_string(),
+        }
+    }
+
+    /// `None` if pmcp should handle `message`; otherwise it was handled
+    /// here, with the response to send, if any
+    async fn intercept(&self, message: &TransportMessage) -> Option<Option<TransportMessage>> {
+        match message {
+            // pmcp's parser yields the second form
+            TransportMessage::Notification(
+                Notification::Client(ClientNotification::Cancelled(params)) | Notification::Cancelled(params),
+            ) => {
+                let request_id = params.request_id.to_string();
+                if let Some(token) = self.calls.lock().unwrap_or_else(|e| e.into_inner()).get(&request_id) {
+                    tracing::info!("🛑 Client cancelled request {}", request_id);
+                    token.cancel();
+                }
+                Some(None)
+            }
+            TransportMessage::Request { id, request: Request::Client(request) } => match &**request {
+                ClientRequest::CallTool(call) if call.name == CANCEL_TASK_TOOL => {
+                    let extra = RequestHandlerExtra::new(id.to_string(), CancellationToken::new());
+                    let result = self.cancel_task.handle(call.arguments.clone(), extra).await;
+                    Some(Some(tool_response(id.clone(), result)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Stops tracking a tool call when dropped
+#[must_use]
+pub struct TrackedCall {
+    calls: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    request_id: String,
+}
+
+impl Drop for TrackedCall {
+    fn drop(&mut self) {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.request_id);
+    }
+}
+
+/// Writing half, shared by the transport and its reader task
+#[derive(Debug)]
+struct Output<W> {
+    writer: tokio::sync::Mutex<W>,
+    /// Whether the client frames messages as one JSON per line
+    line_delimited: AtomicBool,
+}
+
+impl<W: AsyncWrite + Unpin> Output<W> {
+    async fn send(&self, message: TransportMessage) -> pmcp::Result<()> {
+        let json = StdioTransport::serialize_message(&message)?;
+        let line_delimited = self.line_delimited.load(Ordering::Relaxed);
+        let mut frame = if line_delimited {
+            Vec::with_capacity(json.len() + 1)
+        } else {
+            format!("Content-Length: {}\r\n\r\n", json.len()).into_bytes()
+        };
+        frame.extend_from_slice(&json);
+        if line_delimited {
+            frame.push(b'\n');
+        }
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&frame).await.map_err(TransportError::from)?;
+        writer.flush().await.map_err(TransportError::from)?;
+        Ok(())
+    }
+}
+
+/// MCP messages over a stream, framed the way the client frames them:
+/// `Content-Length` headers (like pmcp's stdio transport) or one JSON per line
+#[derive(Debug)]
+pub struct StreamTransport<W> {
+    incoming: mpsc::Receiver<Frame>,
+    output: Arc<Output<W>>,
+    resources_changed: watch::Receiver<()>,
+    /// Cancelled once reading fails, which ends the session
+    closed: CancellationToken,
+}
+
+impl<W: AsyncWrite + Unpin + Send + Sync + Debug + 'static> StreamTransport<W> {
+    pub fn new<R>(
+        reader: R,
+        writer: W,
+        control: SessionControl,
+        resources_changed: watch::Receiver<()>,
+        closed: CancellationToken,
+    ) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let (tx, incoming) = mpsc::channel(16);
+        let output = Arc::new(Output {
+            writer: tokio::sync::Mutex::new(writer),
+            line_delimited: AtomicBool::new(false),
+        });
+        tokio::spawn(read_frames(BufReader::new(reader), tx, output.clone(), control));
+
+        Self {
+            incoming,
+            output,
+            resources_changed,
+            closed,
+        }
+    }
+
+    async fn next_message(&mut self) -> pmcp::Result<TransportMessage> {
+        loop {
+            tokio::select! {
+                frame = self.incoming.recv() => {
+                    let (message, _) = frame.unwrap_or_else(|| Err(TransportError::ConnectionClosed.into()))?;
+
+                    match template_request(&message) {
+                        Some(id) => self.send(templates_response(id)).await?,
+                        None => return Ok(message),
+                    }
+                }
+                Ok(()) = self.resources_changed.changed() => {
+                    tracing::debug!("📣 Sending resources/list_changed");
+                    let notification = Notification::Server(ServerNotification::ResourcesChanged);
+                    self.send(TransportMessage::Notification(notification)).await?;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send + Sync + Debug + 'static> Transport for StreamTransport<W> {
+    async fn send(&mut self, message: TransportMessage) -> pmcp::Result<()> {
+        self.output.send(message).await
+    }
+
+    async fn receive(&mut self) -> pmcp::Result<TransportMessage> {
+        // pmcp stops reading after any receive error, so the session is over
+        let message = self.next_message().await;
+        if message.is_err() {
+            self.closed.cancel();
+        }
+        message
+    }
+
+    async fn close(&mut self) -> pmcp::Result<()> {
+        self.closed.cancel();
+        self.output.writer.lock().await.shutdown().await.map_err(TransportError::from)?;
+        Ok(())
+    }
+
+    fn transport_type(&self) -> &'static str {
+        "stream"
+    }
+}
+
+/// Read messages until the stream ends or a message is malformed, handling
+/// those `control` takes care of right here
+async fn read_frames<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    mut reader: BufReader<R>,
+    tx: mpsc::Sender<Frame>,
+    output: Arc<Output<W>>,
+    control: SessionControl,
+) {
+    loop {
+        let frame = read_frame(&mut reader).await;
+        if let Ok((message, line_delimited)) = &frame {
+            output.line_delimited.store(*line_delimited, Ordering::Relaxed);
+            if let Some(response) = control.intercept(message).await {
+                if let Some(response) = response {
+                    if output.send(response).await.is_err() {
+                        break;
+                    }
+                }
+                continue;
+            }
+        }
+
+        let failed = frame.is_err();
+        if tx.send(frame).await.is_err() || failed {
+            break;
+        }
+    }
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Frame {
+    let mut content_length = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let limit = MAX_FRAME_BYTES as u64 + 1;
+        if (&mut *reader).take(limit).read_line(&mut line).await.map_err(TransportError::from)? == 0 {
+            return Err(TransportError::ConnectionClosed.into());
+        }
+        if line.len() > MAX_FRAME_BYTES {
+            return Err(frame_too_large());
+        }
+
+        let line = line.trim();
+        if content_length.is_none() && line.starts_with('{') {
+            return Ok((StdioTransport::parse_message(line.as_bytes())?, true));
+        }
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let content_length = content_length.unwrap_or_default();
+    if content_length > MAX_FRAME_BYTES {
+        return Err(frame_too_large());
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.map_err(TransportError::from)?;
+    Ok((StdioTransport::parse_message(&body)?, false))
+}
+
+fn frame_too_large() -> pmcp::Error {
+    TransportError::InvalidMessage(format!("message larger than {} bytes", MAX_FRAME_BYTES)).into()
+}
+
+/// ID of a `resources/templates/list` request
+fn template_request(message: &TransportMessage) -> Option<RequestId> {
+    match message {
+        TransportMessage::Request { id, request: Request::Client(request) }
+            if matches!(**request, ClientRequest::ListResourceTemplates(_)) => Some(id.clone()),
+        _ => None,
+    }
+}
+
+fn templates_response(id: RequestId) -> TransportMessage {
+    let result = ListResourceTemplatesResult {
+        resource_templates: resources::templates(),
+        next_cursor: None,
+    };
+    TransportMessage::Response(JSONRPCResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        payload: ResponsePayload::Result(serde_json::to_value(result).unwrap_or_default()),
+    })
+}
+
+/// A tool call response the way pmcp's server words it
+fn tool_response(id: RequestId, result: pmcp::Result<serde_json::Value>) -> TransportMessage {
+    let payload = match result {
+        Ok(value) => {
+            let result = CallToolResult {
+                content: vec![Content::Text { text: value.to_string() }],
+                is_error: false,
+            };
+            ResponsePayload::Result(serde_json::to_value(result).unwrap_or_default())
+        }
+        Err(error) => ResponsePayload::Error(JSONRPCError::from(error)),
+    };
+    TransportMessage::Response(JSONRPCResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_oversized_frames_are_rejected() {
+        let body = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+        let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let (_, line_delimited) = read_frame(&mut BufReader::new(framed.as_bytes())).await.unwrap();
+        assert!(!line_delimited);
+
+        // Refused before anything is allocated for the body
+        let huge = format!("Content-Length: {}\r\n\r\n", usize::MAX);
+        let error = read_frame(&mut BufReader::new(huge.as_bytes())).await.unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{}", error);
+
+        let long_line = format!("{{\"padding\":\"{}\"}}\n", "x".repeat(MAX_FRAME_BYTES));
+        let error = read_frame(&mut BufReader::new(long_line.as_bytes())).await.unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{}", error);
+    }
+}
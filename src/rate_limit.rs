@@ -1,22 +1,26 @@
-use crate::config::{RateLimitingConfig, RateLimit}; // เพิ่ม RateLimit เข้ามา
+use crate::config::{self, RateLimitingConfig, RateLimit}; // เพิ่ม RateLimit เข้ามา
+use crate::usage_store::UsageStore;
 use std::collections::HashMap;
+use std::sync::Arc;
 use chrono::{DateTime, Utc, Duration};
+use serde::{Deserialize, Serialize};
 
 pub struct RateLimitTracker {
     config: RateLimitingConfig,
     usage: HashMap<String, AgentUsage>,
+    store: Option<Arc<UsageStore>>,
 }
 
-#[derive(Debug, Clone)]
-struct AgentUsage {
-    requests_today: u32,
-    requests_this_minute: u32,
-    day_start: DateTime<Utc>,
-    minute_start: DateTime<Utc>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentUsage {
+    pub requests_today: u32,
+    pub requests_this_minute: u32,
+    pub day_start: DateTime<Utc>,
+    pub minute_start: DateTime<Utc>,
 }
 
 impl AgentUsage {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let now = Utc::now();
         Self {
             requests_today: 0,
@@ -41,13 +45,59 @@ impl AgentUsage {
             self.minute_start = now;
         }
     }
+
+    /// Remaining (daily, per-minute) requests, treating expired windows as reset
+    fn remaining(&self, limit: &RateLimit) -> (u32, u32) {
+        let mut usage = self.clone();
+        usage.reset_if_needed();
+        (
+            limit.requests_per_day.saturating_sub(usage.requests_today),
+            limit.requests_per_minute.saturating_sub(usage.requests_this_minute),
+        )
+    }
+}
+
+impl Default for AgentUsage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RateLimitTracker {
     pub fn new(config: RateLimitingConfig) -> Self {
+        // โหลด usage เดิมจากดิสก์ เพื่อไม่ให้ quota รีเซ็ตเมื่อรีสตาร์ท server
+        let store = if config.track_usage {
+            let path = config::expand_tilde(&config.usage_db_path);
+            match UsageStore::open(&path) {
+                Ok(store) => Some(Arc::new(store)),
+                Err(e) => {
+                    tracing::warn!(
+                        "⚠️  Usage store unavailable at {:?}, tracking in memory only: {:#}",
+                        path, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let usage = match &store {
+            Some(store) => store.load().unwrap_or_else(|e| {
+                tracing::warn!("⚠️  Failed to load usage from {:?}: {:#}", store.path(), e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+        if let Some(store) = &store {
+            tracing::info!("📈 Loaded usage for {} agents from {:?}", usage.len(), store.path());
+        }
+
         Self {
             config,
-            usage: HashMap::new(),
+            usage,
+            store,
         }
     }
 
@@ -59,9 +109,67 @@ impl RateLimitTracker {
             return true;
         }
 
-        let usage = self.usage
+        // Persist through the store so every process shares the same counters
+        if let Some(store) = &self.store {
+            let mut usage = self.usage.clone();
+            let (id, limit) = (agent_id.to_string(), agent_limit.clone());
+            let result = blocking(store, move |store| {
+                let allowed = store.update(&mut usage, |usage| Self::try_increment(usage, &id, &limit));
+                (usage, allowed)
+            }).await;
+
+            match result {
+                Ok((usage, Ok(allowed))) => {
+                    self.usage = usage;
+                    return allowed;
+                }
+                Ok((usage, Err(e))) => {
+                    self.usage = usage;
+                    tracing::warn!("⚠️  Failed to persist usage for {}: {:#}", agent_id, e);
+                }
+                Err(e) => tracing::warn!("⚠️  Failed to persist usage for {}: {:#}", agent_id, e),
+            }
+        }
+
+        Self::try_increment(&mut self.usage, agent_id, agent_limit)
+    }
+
+    /// Reload counters written by other processes sharing the usage file
+    pub async fn refresh(&mut self) {
+        if let Some(store) = &self.store {
+            match blocking(store, UsageStore::load).await.and_then(|loaded| loaded) {
+                Ok(usage) => self.usage = usage,
+                Err(e) => tracing::warn!("⚠️  Failed to refresh usage: {:#}", e),
+            }
+        }
+    }
+
+    /// Remaining (daily, per-minute) requests for an agent, without consuming any
+    pub fn remaining(&self, agent_id: &str, agent_limit: &RateLimit) -> (u32, u32) {
+        match self.usage.get(agent_id) {
+            Some(usage) => usage.remaining(agent_limit),
+            None => (agent_limit.requests_per_day, agent_limit.requests_per_minute),
+        }
+    }
+
+    /// Check if agent could make a request right now, without consuming quota
+    pub fn has_quota(&self, agent_id: &str, agent_limit: &RateLimit) -> bool {
+        if !self.config.track_usage {
+            return true;
+        }
+
+        let (daily, minute) = self.remaining(agent_id, agent_limit);
+        daily > 0 && minute > 0
+    }
+
+    fn try_increment(
+        usage: &mut HashMap<String, AgentUsage>,
+        agent_id: &str,
+        agent_limit: &RateLimit,
+    ) -> bool {
+        let usage = usage
             .entry(agent_id.to_string())
-            .or_insert_with(AgentUsage::new);
+            .or_default();
 
         usage.reset_if_needed();
 
@@ -102,6 +210,7 @@ impl RateLimitTracker {
     }
 
     /// Get current usage for an agent
+    #[cfg(test)]
     pub fn get_usage(&self, agent_id: &str) -> Option<(u32, u32)> {
         self.usage.get(agent_id).map(|usage| {
             (usage.requests_today, usage.requests_this_minute)
@@ -109,22 +218,45 @@ impl RateLimitTracker {
     }
 
     /// Reset all usage counters (for testing)
+    #[cfg(test)]
     pub fn reset_all(&mut self) {
         self.usage.clear();
+        if let Some(store) = &self.store {
+            if let Err(e) = store.clear() {
+                tracing::warn!("⚠️  Failed to clear usage store: {:#}", e);
+            }
+        }
     }
 }
 
+/// Run `f` on the blocking pool: the store takes a file lock, which another
+/// process may hold, and does synchronous file I/O
+async fn blocking<R: Send + 'static>(
+    store: &Arc<UsageStore>,
+    f: impl FnOnce(&UsageStore) -> R + Send + 'static,
+) -> anyhow::Result<R> {
+    let store = store.clone();
+    Ok(tokio::task::spawn_blocking(move || f(&store)).await?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::LoadBalancingStrategy;
+    use tempfile::TempDir;
+
+    fn test_config(temp: &TempDir, track_usage: bool) -> RateLimitingConfig {
+        RateLimitingConfig {
+            strategy: LoadBalancingStrategy::RoundRobin,
+            track_usage,
+            usage_db_path: temp.path().join("usage.db").to_string_lossy().into_owned(),
+        }
+    }
 
     #[tokio::test]
     async fn test_rate_limiting_with_specific_limits() {
-        let config = RateLimitingConfig {
-            strategy: "round-robin".to_string(),
-            track_usage: true,
-            usage_db_path: None, // แก้ไขให้เป็น Option ตาม config.rs
-        };
+        let temp = TempDir::new().unwrap();
+        let config = test_config(&temp, true);
 
         let mut tracker = RateLimitTracker::new(config);
         
@@ -145,11 +277,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_minute_limit_is_enforced() {
-        let config = RateLimitingConfig {
-            strategy: "round-robin".to_string(),
-            track_usage: true,
-            usage_db_path: None,
-        };
+        let temp = TempDir::new().unwrap();
+        let config = test_config(&temp, true);
 
         let mut tracker = RateLimitTracker::new(config);
 
@@ -175,11 +304,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_tracking_disabled() {
-        let config = RateLimitingConfig {
-            strategy: "round-robin".to_string(),
-            track_usage: false, // ปิดการติดตาม
-            usage_db_path: None,
-        };
+        let temp = TempDir::new().unwrap();
+        let config = test_config(&temp, false);
 
         let mut tracker = RateLimitTracker::new(config);
         let agent_limit = RateLimit { requests_per_minute: 1, requests_per_day: 1 };
@@ -188,4 +314,95 @@ mod tests {
         assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
         assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
     }
+
+    #[tokio::test]
+    async fn test_usage_survives_restart() {
+        let temp = TempDir::new().unwrap();
+        let agent_limit = RateLimit { requests_per_minute: 10, requests_per_day: 2 };
+
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+        drop(tracker);
+
+        // A fresh tracker (e.g. after a server restart) keeps the daily quota
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        assert_eq!(tracker.get_usage("test-agent"), Some((2, 2)));
+        assert!(!tracker.check_and_increment("test-agent", &agent_limit).await);
+    }
+
+    #[tokio::test]
+    async fn test_has_quota_does_not_consume() {
+        let temp = TempDir::new().unwrap();
+        let agent_limit = RateLimit { requests_per_minute: 1, requests_per_day: 10 };
+
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        assert!(tracker.has_quota("test-agent", &agent_limit));
+        assert!(tracker.has_quota("test-agent", &agent_limit));
+
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+        assert!(!tracker.has_quota("test-agent", &agent_limit));
+        assert_eq!(tracker.remaining("test-agent", &agent_limit), (9, 0));
+    }
+
+    #[tokio::test]
+    async fn test_locked_store_does_not_block_runtime() {
+        let temp = TempDir::new().unwrap();
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+
+        // Another process holds the store
+        let lock = std::fs::File::create(temp.path().join("usage.db.lock")).unwrap();
+        lock.lock().unwrap();
+
+        let limit = RateLimit::default();
+        let increment = tracker.check_and_increment("agent", &limit);
+        tokio::pin!(increment);
+        tokio::select! {
+            _ = &mut increment => panic!("the store lock is held"),
+            _ = tokio::time::sleep(std::time::Duration::from_millis(100)) => {}
+        }
+
+        lock.unlock().unwrap();
+        assert!(increment.await);
+    }
+
+    #[tokio::test]
+    async fn test_reset_all_clears_store() {
+        let temp = TempDir::new().unwrap();
+        let agent_limit = RateLimit { requests_per_minute: 1, requests_per_day: 1 };
+
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+        tracker.reset_all();
+
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        assert!(tracker.get_usage("test-agent").is_none());
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+    }
+
+    #[tokio::test]
+    async fn test_failed_persist_counts_once() {
+        let temp = TempDir::new().unwrap();
+        let agent_limit = RateLimit { requests_per_minute: 10, requests_per_day: 10 };
+
+        let mut tracker = RateLimitTracker::new(test_config(&temp, true));
+        std::fs::create_dir(temp.path().join("usage.db.tmp")).unwrap();
+
+        assert!(tracker.check_and_increment("test-agent", &agent_limit).await);
+        assert_eq!(tracker.get_usage("test-agent"), Some((1, 1)));
+    }
+
+    #[tokio::test]
+    async fn test_trackers_share_usage_file() {
+        let temp = TempDir::new().unwrap();
+        let agent_limit = RateLimit { requests_per_minute: 10, requests_per_day: 3 };
+
+        let mut first = RateLimitTracker::new(test_config(&temp, true));
+        let mut second = RateLimitTracker::new(test_config(&temp, true));
+
+        assert!(first.check_and_increment("test-agent", &agent_limit).await);
+        assert!(second.check_and_increment("test-agent", &agent_limit).await);
+        assert!(first.check_and_increment("test-agent", &agent_limit).await);
+        assert!(!second.check_and_increment("test-agent", &agent_limit).await);
+    }
 }
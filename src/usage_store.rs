@@ -0,0 +1,208 @@
+//! Durable storage for rate-limit usage counters.
+//!
+//! Counters are kept in a JSON file at `rate_limiting.usage_db_path`. Every
+//! read-modify-write happens while holding an exclusive lock on a sibling
+//! `.lock` file, so several orchestrator processes can share one file without
+//! losing increments. The data file itself is replaced atomically via rename.
+
+use crate::rate_limit::AgentUsage;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+pub struct UsageStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+}
+
+impl UsageStore {
+    /// Open (or create) the usage store at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create usage directory: {:?}", parent))?;
+        }
+
+        let lock_path = sibling_path(&path, "lock");
+
+        Ok(Self { path, lock_path })
+    }
+
+    /// Path of the usage data file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load all counters from disk
+    pub fn load(&self) -> Result<HashMap<String, AgentUsage>> {
+        let _guard = self.lock()?;
+        self.read()
+    }
+
+    /// Refresh `usage` from disk, apply `f` and persist the result.
+    ///
+    /// The whole operation runs under the store lock, so concurrent processes
+    /// always observe each other's increments. On error `usage` holds none of
+    /// `f`'s changes, so the caller can apply them in memory instead.
+    pub fn update<R>(
+        &self,
+        usage: &mut HashMap<String, AgentUsage>,
+        f: impl FnOnce(&mut HashMap<String, AgentUsage>) -> R,
+    ) -> Result<R> {
+        let _guard = self.lock()?;
+
+        let stored = self.read()?;
+        let mut updated = stored.clone();
+        let result = f(&mut updated);
+        // Keep the freshest counters we have even if they cannot be saved
+        match self.write(&updated) {
+            Ok(()) => *usage = updated,
+            Err(e) => {
+                *usage = stored;
+                return Err(e);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Remove every stored counter
+    #[cfg(test)]
+    pub fn clear(&self) -> Result<()> {
+        let _guard = self.lock()?;
+        self.write(&HashMap::new())
+    }
+
+    fn lock(&self) -> Result<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.lock_path)
+            .with_context(|| format!("Failed to open usage lock file: {:?}", self.lock_path))?;
+
+        // Released when the returned handle is dropped
+        file.lock()
+            .with_context(|| format!("Failed to lock usage file: {:?}", self.lock_path))?;
+
+        Ok(file)
+    }
+
+    fn read(&self) -> Result<HashMap<String, AgentUsage>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read usage file: {:?}", self.path))
+            }
+        };
+
+        if content.trim().is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        match serde_json::from_str(&content) {
+            Ok(usage) => Ok(usage),
+            Err(e) => {
+                tracing::warn!(
+                    "⚠️  Usage file {:?} is corrupt, starting with fresh counters: {}",
+                    self.path, e
+                );
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    fn write(&self, usage: &HashMap<String, AgentUsage>) -> Result<()> {
+        let tmp_path = sibling_path(&self.path, "tmp");
+        let content = serde_json::to_string_pretty(usage)?;
+
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write usage file: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace usage file: {:?}", self.path))?;
+
+        Ok(())
+    }
+}
+
+/// `usage.db` -> `usage.db.<suffix>`
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_update_persists_counters() {
+        let temp = TempDir::new().unwrap();
+        let store = UsageStore::open(temp.path().join("nested/usage.db")).unwrap();
+
+        let mut usage = HashMap::new();
+        store.update(&mut usage, |usage| {
+            usage.entry("agent".to_string()).or_default().requests_today = 3;
+        }).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.get("agent").unwrap().requests_today, 3);
+    }
+
+    #[test]
+    fn test_update_sees_other_writers() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("usage.db");
+        let first = UsageStore::open(&path).unwrap();
+        let second = UsageStore::open(&path).unwrap();
+
+        let increment = |usage: &mut HashMap<String, AgentUsage>| {
+            usage.entry("agent".to_string()).or_default().requests_today += 1;
+        };
+
+        let mut first_usage = HashMap::new();
+        let mut second_usage = HashMap::new();
+        first.update(&mut first_usage, increment).unwrap();
+        second.update(&mut second_usage, increment).unwrap();
+
+        assert_eq!(second_usage.get("agent").unwrap().requests_today, 2);
+    }
+
+    #[test]
+    fn test_failed_write_leaves_usage_unchanged() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("usage.db");
+        let store = UsageStore::open(&path).unwrap();
+
+        let mut usage = HashMap::new();
+        store.update(&mut usage, |usage| {
+            usage.entry("agent".to_string()).or_default().requests_today = 1;
+        }).unwrap();
+
+        // The temp file cannot be created, so the rename never happens
+        fs::create_dir(sibling_path(&path, "tmp")).unwrap();
+        let result = store.update(&mut usage, |usage| {
+            usage.entry("agent".to_string()).or_default().requests_today += 1;
+        });
+
+        assert!(result.is_err());
+        assert_eq!(usage.get("agent").unwrap().requests_today, 1);
+    }
+
+    #[test]
+    fn test_corrupt_file_starts_fresh() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("usage.db");
+        fs::write(&path, "not json").unwrap();
+
+        let store = UsageStore::open(&path).unwrap();
+        assert!(store.load().unwrap().is_empty());
+    }
+}